@@ -0,0 +1,158 @@
+/*
+ * dxx.h - C ABI of the dxx library.
+ *
+ * dxx reads and writes .DXX audio files (DSA/DFA/DDA text, DSB/DFB/DDB and the other
+ * binary types, DXH, WAV and NPY). Samples are exchanged as doubles; the samples of
+ * a multichannel file are interleaved.
+ *
+ * Link against libdxx_capi (dxx_capi.dll on Windows). The header is usable from C,
+ * C++ and MATLAB MEX files.
+ *
+ * Conventions:
+ *   - Paths are NUL-terminated UTF-8 strings. The data type is given by the suffix.
+ *   - Every function except dxx_last_error, dxx_dtype_name and dxx_free returns
+ *     a dxx_status. On failure, dxx_last_error returns its message.
+ *   - The functions are thread-safe; the last error is kept per thread.
+ */
+#ifndef DXX_H
+#define DXX_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* dxx_status is the result of a call. */
+typedef enum dxx_status {
+    DXX_OK = 0,
+    /* A NULL pointer, a path that is not UTF-8 or an unknown enum value. */
+    DXX_ERROR_INVALID_ARGUMENT = 1,
+    /* An I/O failure such as a missing file. */
+    DXX_ERROR_IO = 2,
+    /* A file suffix that is not a DXX type. */
+    DXX_ERROR_UNSUPPORTED_TYPE = 3,
+    /* A text file with a line that cannot be parsed. */
+    DXX_ERROR_PARSE = 4,
+    /* Binary data whose length is not a multiple of the sample width. */
+    DXX_ERROR_SIZE_MISMATCH = 5,
+    /* A DXH/WAV/NPY header or a sidecar metadata file that cannot be decoded. */
+    DXX_ERROR_INVALID_FILE = 6,
+    /* A number of samples or columns that does not fit the channel count. */
+    DXX_ERROR_CHANNELS = 7,
+    /* A non-finite sample or a silent signal that cannot be written. */
+    DXX_ERROR_INVALID_SAMPLES = 8,
+    /* A caller-provided buffer that cannot hold the samples. */
+    DXX_ERROR_BUFFER_TOO_SMALL = 9,
+    /* A bug of the library caught at the boundary. */
+    DXX_ERROR_PANIC = 10,
+    /* Any other failure. */
+    DXX_ERROR_OTHER = 11
+} dxx_status;
+
+/* dxx_dtype is the data type of a file. */
+typedef enum dxx_dtype {
+    DXX_DSA = 0,  /* 16-bit integer text */
+    DXX_DFA = 1,  /* 32-bit float text */
+    DXX_DDA = 2,  /* 64-bit float text */
+    DXX_DSB = 3,  /* 16-bit integer */
+    DXX_DFB = 4,  /* 32-bit float */
+    DXX_DDB = 5,  /* 64-bit float */
+    DXX_DCB = 6,  /* 8-bit integer */
+    DXX_DTB = 7,  /* 24-bit integer */
+    DXX_DLB = 8,  /* 32-bit integer */
+    DXX_DUB = 9,  /* G.711 mu-law */
+    DXX_DAB = 10, /* G.711 A-law */
+    DXX_DXH = 11, /* self-describing container */
+    DXX_WAV = 12, /* RIFF/WAVE */
+    DXX_NPY = 13  /* NumPy array */
+} dxx_dtype;
+
+/* dxx_scaling is how samples are scaled when they are written. */
+typedef enum dxx_scaling {
+    /* Peak-normalize to the standard amplitude of the type: 32767 for the 16-bit
+     * types and 10000 for the float types. This is what the tools do. */
+    DXX_SCALING_AUTO = 0,
+    /* Write the samples as they are. */
+    DXX_SCALING_RAW = 1,
+    /* Normalize the absolute peak to `amount`. */
+    DXX_SCALING_PEAK = 2,
+    /* Multiply the samples by `amount`. */
+    DXX_SCALING_GAIN = 3,
+    /* Normalize the RMS to `amount`. */
+    DXX_SCALING_RMS = 4
+} dxx_scaling;
+
+/* dxx_file_info describes a file. */
+typedef struct dxx_file_info {
+    /* The dxx_dtype of the file suffix. */
+    int dtype;
+    /* The number of samples of all channels. */
+    size_t len;
+    /* The number of interleaved channels. */
+    uint16_t channels;
+    /* The sampling frequency in Hz, or 0 if it is unknown. */
+    uint32_t sampling_rate;
+} dxx_file_info;
+
+/*
+ * dxx_last_error returns the message of the last failure on the calling thread,
+ * or NULL if no call has failed. The message stays valid until the next failing
+ * call on the same thread.
+ */
+const char *dxx_last_error(void);
+
+/* dxx_dtype_name returns the name of `dtype` such as "DSB", or NULL for an unknown value. */
+const char *dxx_dtype_name(int dtype);
+
+/* dxx_dtype_from_path stores the dxx_dtype of the suffix of `path` in `dtype`. */
+dxx_status dxx_dtype_from_path(const char *path, int *dtype);
+
+/*
+ * dxx_info stores the description of the file at `path` in `info`. The samples of a
+ * binary file are not read; a DSA/DFA/DDA text file is scanned to count them.
+ * The channel count and the sampling rate come from the header of DXH and WAV files
+ * and from the sidecar metadata file (`path` + ".json") of the others; an NPY file
+ * without one gets the channel count of its shape.
+ */
+dxx_status dxx_info(const char *path, dxx_file_info *info);
+
+/*
+ * dxx_read reads the samples of the file at `path` into `buf`, which holds `capacity`
+ * samples, and stores the number of samples in `len`.
+ * The length is checked before the samples are decoded: if `buf` is too small, nothing
+ * is copied, `len` is set to the number of samples and DXX_ERROR_BUFFER_TOO_SMALL is
+ * returned, so the call can be repeated with a larger buffer.
+ */
+dxx_status dxx_read(const char *path, double *buf, size_t capacity, size_t *len);
+
+/*
+ * dxx_read_alloc reads the samples of the file at `path` into a buffer allocated by
+ * the library. The buffer is stored in `data` and its number of samples in `len`.
+ * Release the buffer with dxx_free, not with free().
+ */
+dxx_status dxx_read_alloc(const char *path, double **data, size_t *len);
+
+/* dxx_free releases a buffer of dxx_read_alloc with its `len`. It does nothing for NULL. */
+void dxx_free(double *data, size_t len);
+
+/*
+ * dxx_write writes `len` samples of `data` to the file at `path` with `scaling`, one
+ * of the dxx_scaling values. `amount` is used by the peak, gain and RMS scalings.
+ */
+dxx_status dxx_write(const char *path, const double *data, size_t len, int scaling, double amount);
+
+/*
+ * dxx_write_with_metadata is dxx_write for `channels` interleaved channels. The channel
+ * count and `sampling_rate` (unless it is 0) are kept in the header of DXH and WAV
+ * files and in a sidecar metadata file otherwise.
+ */
+dxx_status dxx_write_with_metadata(const char *path, const double *data, size_t len, int scaling, double amount,
+                                   uint16_t channels, uint32_t sampling_rate);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* DXX_H */
@@ -0,0 +1,566 @@
+//! C ABI of the dxx library.
+//!
+//! The functions are declared in `include/dxx.h`, which documents them for C, C++ and
+//! MATLAB MEX. Every function returns a `DxxStatus`; `DXX_OK` is success and the
+//! message of any other status is returned by `dxx_last_error` on the same thread.
+//! Panics are caught at the boundary and reported as `DXX_ERROR_PANIC`.
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_double, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use std::slice;
+
+use dxx::{DType, Error, Metadata, Scaling, WriteOptions};
+
+/// DxxStatus is `dxx_status` of the header, the result of a call.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DxxStatus {
+    /// Ok is success.
+    Ok = 0,
+    /// InvalidArgument is a null pointer, a path that is not UTF-8 or an unknown enum value.
+    InvalidArgument = 1,
+    /// Io is an I/O failure.
+    Io = 2,
+    /// UnsupportedType is a file suffix that is not a DXX type.
+    UnsupportedType = 3,
+    /// Parse is a text file with a line that cannot be parsed.
+    Parse = 4,
+    /// SizeMismatch is binary data whose length is not a multiple of the sample width.
+    SizeMismatch = 5,
+    /// InvalidFile is a header, chunk or sidecar file that cannot be decoded.
+    InvalidFile = 6,
+    /// Channels is a number of samples or columns that does not fit the channels.
+    Channels = 7,
+    /// InvalidSamples is a non-finite sample or a silent signal that cannot be written.
+    InvalidSamples = 8,
+    /// BufferTooSmall is a caller-provided buffer that cannot hold the samples.
+    BufferTooSmall = 9,
+    /// Panic is a bug of the library caught at the boundary.
+    Panic = 10,
+    /// Other is any other failure.
+    Other = 11,
+}
+
+/// DXX_OK is DxxStatus::Ok.
+pub const DXX_OK: DxxStatus = DxxStatus::Ok;
+/// DXX_ERROR_INVALID_ARGUMENT is DxxStatus::InvalidArgument.
+pub const DXX_ERROR_INVALID_ARGUMENT: DxxStatus = DxxStatus::InvalidArgument;
+/// DXX_ERROR_IO is DxxStatus::Io.
+pub const DXX_ERROR_IO: DxxStatus = DxxStatus::Io;
+/// DXX_ERROR_UNSUPPORTED_TYPE is DxxStatus::UnsupportedType.
+pub const DXX_ERROR_UNSUPPORTED_TYPE: DxxStatus = DxxStatus::UnsupportedType;
+/// DXX_ERROR_PARSE is DxxStatus::Parse.
+pub const DXX_ERROR_PARSE: DxxStatus = DxxStatus::Parse;
+/// DXX_ERROR_SIZE_MISMATCH is DxxStatus::SizeMismatch.
+pub const DXX_ERROR_SIZE_MISMATCH: DxxStatus = DxxStatus::SizeMismatch;
+/// DXX_ERROR_INVALID_FILE is DxxStatus::InvalidFile.
+pub const DXX_ERROR_INVALID_FILE: DxxStatus = DxxStatus::InvalidFile;
+/// DXX_ERROR_CHANNELS is DxxStatus::Channels.
+pub const DXX_ERROR_CHANNELS: DxxStatus = DxxStatus::Channels;
+/// DXX_ERROR_INVALID_SAMPLES is DxxStatus::InvalidSamples.
+pub const DXX_ERROR_INVALID_SAMPLES: DxxStatus = DxxStatus::InvalidSamples;
+/// DXX_ERROR_BUFFER_TOO_SMALL is DxxStatus::BufferTooSmall.
+pub const DXX_ERROR_BUFFER_TOO_SMALL: DxxStatus = DxxStatus::BufferTooSmall;
+/// DXX_ERROR_PANIC is DxxStatus::Panic.
+pub const DXX_ERROR_PANIC: DxxStatus = DxxStatus::Panic;
+/// DXX_ERROR_OTHER is DxxStatus::Other.
+pub const DXX_ERROR_OTHER: DxxStatus = DxxStatus::Other;
+
+/// DXX_SCALING_AUTO peak-normalizes to the standard amplitude of the data type.
+pub const DXX_SCALING_AUTO: c_int = 0;
+/// DXX_SCALING_RAW writes the samples as they are.
+pub const DXX_SCALING_RAW: c_int = 1;
+/// DXX_SCALING_PEAK normalizes the absolute peak to `amount`.
+pub const DXX_SCALING_PEAK: c_int = 2;
+/// DXX_SCALING_GAIN multiplies the samples by `amount`.
+pub const DXX_SCALING_GAIN: c_int = 3;
+/// DXX_SCALING_RMS normalizes the RMS to `amount`.
+pub const DXX_SCALING_RMS: c_int = 4;
+
+/// DTYPES lists the data types in the order of the `dxx_dtype` enum of the header.
+const DTYPES: [DType; 14] = [
+    DType::DSA,
+    DType::DFA,
+    DType::DDA,
+    DType::DSB,
+    DType::DFB,
+    DType::DDB,
+    DType::DCB,
+    DType::DTB,
+    DType::DLB,
+    DType::DUB,
+    DType::DAB,
+    DType::DXH,
+    DType::WAV,
+    DType::NPY,
+];
+
+/// DTYPE_NAMES are the names of DTYPES as C strings.
+const DTYPE_NAMES: [&[u8]; 14] = [
+    b"DSA\0", b"DFA\0", b"DDA\0", b"DSB\0", b"DFB\0", b"DDB\0", b"DCB\0", b"DTB\0", b"DLB\0", b"DUB\0", b"DAB\0", b"DXH\0", b"WAV\0", b"NPY\0",
+];
+
+/// DxxFileInfo is `dxx_file_info` of the header.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DxxFileInfo {
+    /// dtype is the `dxx_dtype` of the file suffix.
+    pub dtype: c_int,
+    /// len is the number of samples of all channels.
+    pub len: usize,
+    /// channels is the number of interleaved channels.
+    pub channels: u16,
+    /// sampling_rate is the sampling frequency in Hz, or 0 if it is unknown.
+    pub sampling_rate: u32,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Failure is a status code and its message.
+struct Failure {
+    status: DxxStatus,
+    message: String,
+}
+
+impl Failure {
+    fn new<S: Into<String>>(status: DxxStatus, message: S) -> Self {
+        Failure { status, message: message.into() }
+    }
+}
+
+impl From<Error> for Failure {
+    fn from(e: Error) -> Self {
+        let status = match e {
+            Error::Io { .. } => DXX_ERROR_IO,
+            Error::UnsupportedType(_) => DXX_ERROR_UNSUPPORTED_TYPE,
+            Error::Parse { .. } | Error::ColumnMismatch { .. } => DXX_ERROR_PARSE,
+            Error::SizeMismatch { .. } => DXX_ERROR_SIZE_MISMATCH,
+            Error::InvalidHeader(_) | Error::InvalidNpy(_) | Error::InvalidWav(_) | Error::Decode { .. } | Error::Metadata { .. } => DXX_ERROR_INVALID_FILE,
+            Error::ChannelMismatch { .. } | Error::ChannelCount { .. } | Error::ChannelLength { .. } => DXX_ERROR_CHANNELS,
+            Error::NonFinite { .. } | Error::Silent => DXX_ERROR_INVALID_SAMPLES,
+            _ => DXX_ERROR_OTHER,
+        };
+        Failure::new(status, e.to_string())
+    }
+}
+
+/// run calls f, records the failure of it for dxx_last_error and returns its status code.
+fn run<F: FnOnce() -> Result<(), Failure>>(f: F) -> DxxStatus {
+    let failure = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return DXX_OK,
+        Ok(Err(failure)) => failure,
+        Err(_) => Failure::new(DXX_ERROR_PANIC, "panic in the dxx library"),
+    };
+    // A message cannot hold a NUL byte in C, so it is cut at the first one.
+    let mut message = failure.message.into_bytes();
+    message.truncate(message.iter().position(|&b| b == 0).unwrap_or(message.len()));
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+    failure.status
+}
+
+/// path_of converts a NUL-terminated UTF-8 string to a path.
+unsafe fn path_of(path: *const c_char) -> Result<PathBuf, Failure> {
+    if path.is_null() {
+        return Err(Failure::new(DXX_ERROR_INVALID_ARGUMENT, "path is NULL"));
+    }
+    let path = CStr::from_ptr(path).to_str().map_err(|_| Failure::new(DXX_ERROR_INVALID_ARGUMENT, "path is not UTF-8"))?;
+    Ok(PathBuf::from(path))
+}
+
+/// non_null fails unless the pointer named `name` is set.
+fn non_null<T>(p: *const T, name: &str) -> Result<(), Failure> {
+    if p.is_null() {
+        return Err(Failure::new(DXX_ERROR_INVALID_ARGUMENT, format!("{} is NULL", name)));
+    }
+    Ok(())
+}
+
+fn index_of(dtype: DType) -> c_int {
+    DTYPES.iter().position(|&d| d == dtype).expect("every DType is listed in DTYPES") as c_int
+}
+
+fn scaling_of(scaling: c_int, amount: c_double) -> Result<Scaling, Failure> {
+    match scaling {
+        DXX_SCALING_AUTO => Ok(Scaling::Auto),
+        DXX_SCALING_RAW => Ok(Scaling::Raw),
+        DXX_SCALING_PEAK => Ok(Scaling::Peak(amount)),
+        DXX_SCALING_GAIN => Ok(Scaling::Gain(amount)),
+        DXX_SCALING_RMS => Ok(Scaling::Rms(amount)),
+        _ => Err(Failure::new(DXX_ERROR_INVALID_ARGUMENT, format!("unknown scaling: {}", scaling))),
+    }
+}
+
+/// dxx_last_error returns the message of the last failure on the calling thread.
+///
+/// It returns NULL if no call has failed on the thread. The message stays valid until
+/// the next failing call on the same thread.
+#[no_mangle]
+pub extern "C" fn dxx_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |message| message.as_ptr()))
+}
+
+/// dxx_dtype_name returns the name of the data type, or NULL for an unknown value.
+#[no_mangle]
+pub extern "C" fn dxx_dtype_name(dtype: c_int) -> *const c_char {
+    usize::try_from(dtype)
+        .ok()
+        .and_then(|i| DTYPE_NAMES.get(i))
+        .map_or(ptr::null(), |name| name.as_ptr() as *const c_char)
+}
+
+/// dxx_dtype_from_path stores the data type of the file suffix of `path` in `dtype`.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `dtype` must point to a writable int.
+#[no_mangle]
+pub unsafe extern "C" fn dxx_dtype_from_path(path: *const c_char, dtype: *mut c_int) -> DxxStatus {
+    run(|| {
+        let path = path_of(path)?;
+        non_null(dtype, "dtype")?;
+        let found = DType::from_path(path).map_err(Error::from)?;
+        *dtype = index_of(found);
+        Ok(())
+    })
+}
+
+/// dxx_info stores the description of the file at `path` in `info`.
+///
+/// Only the header, the sidecar file or the NPY shape of a binary file is read;
+/// a text file is scanned to count its samples and columns.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `info` must point to a writable `dxx_file_info`.
+#[no_mangle]
+pub unsafe extern "C" fn dxx_info(path: *const c_char, info: *mut DxxFileInfo) -> DxxStatus {
+    run(|| {
+        let path = path_of(path)?;
+        non_null(info, "info")?;
+        let dtype = DType::from_path(&path).map_err(Error::from)?;
+        // The columns of a text file give its channels, so it is read; the length of the
+        // others follows from their size, header or shape.
+        let (len, metadata) = if dtype.is_binary() {
+            (dxx::len_samples(&path)?, dxx::read_metadata(&path)?.unwrap_or_default())
+        } else {
+            let (data, metadata) = dxx::read_file_with_metadata(&path)?;
+            (data.len() as u64, metadata)
+        };
+        let len = usize::try_from(len).map_err(|_| Failure::new(DXX_ERROR_OTHER, "the file is too long for size_t"))?;
+        metadata.validate(len)?;
+        *info = DxxFileInfo {
+            dtype: index_of(dtype),
+            len,
+            channels: metadata.channels,
+            sampling_rate: metadata.sampling_rate.unwrap_or(0),
+        };
+        Ok(())
+    })
+}
+
+/// dxx_read reads the samples of the file at `path` into the caller-provided `buf`
+/// of `capacity` samples and stores the number of samples in `len`.
+///
+/// If `buf` is too small, nothing is decoded or copied, `len` is set to the number of
+/// samples and `DXX_ERROR_BUFFER_TOO_SMALL` is returned.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string, `buf` must point to `capacity` writable
+/// doubles (or be NULL if `capacity` is 0) and `len` must point to a writable size_t.
+#[no_mangle]
+pub unsafe extern "C" fn dxx_read(path: *const c_char, buf: *mut c_double, capacity: usize, len: *mut usize) -> DxxStatus {
+    run(|| {
+        let path = path_of(path)?;
+        non_null(len, "len")?;
+        let too_small = |n: usize| Failure::new(DXX_ERROR_BUFFER_TOO_SMALL, format!("{}: {} samples do not fit a buffer of {}", path.display(), n, capacity));
+        // The length is known before the samples are decoded, so a small buffer costs
+        // no decoding; it is checked again in case the file changed in between.
+        let want = usize::try_from(dxx::len_samples(&path)?).map_err(|_| Failure::new(DXX_ERROR_OTHER, "the file is too long for size_t"))?;
+        if want > capacity {
+            *len = want;
+            return Err(too_small(want));
+        }
+        let data = dxx::read_file(&path)?;
+        *len = data.len();
+        if data.len() > capacity {
+            return Err(too_small(data.len()));
+        }
+        if !data.is_empty() {
+            non_null(buf, "buf")?;
+            slice::from_raw_parts_mut(buf, data.len()).copy_from_slice(&data);
+        }
+        Ok(())
+    })
+}
+
+/// dxx_read_alloc reads the samples of the file at `path` into a buffer allocated by
+/// the library, which is stored in `data` with its number of samples in `len`.
+/// The buffer must be released with dxx_free.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string, and `data` and `len` must point to
+/// a writable pointer and size_t.
+#[no_mangle]
+pub unsafe extern "C" fn dxx_read_alloc(path: *const c_char, data: *mut *mut c_double, len: *mut usize) -> DxxStatus {
+    run(|| {
+        let path = path_of(path)?;
+        non_null(data, "data")?;
+        non_null(len, "len")?;
+        let samples = dxx::read_file(&path)?.into_boxed_slice();
+        *len = samples.len();
+        *data = Box::into_raw(samples) as *mut c_double;
+        Ok(())
+    })
+}
+
+/// dxx_free releases a buffer of `len` samples allocated by dxx_read_alloc.
+/// It does nothing for NULL.
+///
+/// # Safety
+///
+/// `data` and `len` must be the ones returned by dxx_read_alloc, and `data` must not
+/// be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn dxx_free(data: *mut c_double, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// dxx_write writes `len` samples of `data` to the file at `path` of the type given by
+/// its suffix, scaled with `scaling` (one of the `DXX_SCALING_*` values).
+/// `amount` is the value of the peak, gain and RMS scalings and is ignored otherwise.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `data` must point to `len` readable
+/// doubles (or be NULL if `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn dxx_write(path: *const c_char, data: *const c_double, len: usize, scaling: c_int, amount: c_double) -> DxxStatus {
+    run(|| {
+        let path = path_of(path)?;
+        let src = samples_of(data, len)?;
+        let options = WriteOptions::new().with_scaling(scaling_of(scaling, amount)?);
+        dxx::write_file_with(&path, src, &options)?;
+        Ok(())
+    })
+}
+
+/// dxx_write_with_metadata is dxx_write for `channels` interleaved channels that also
+/// keeps the channel count and `sampling_rate` (unless it is 0) as metadata.
+///
+/// # Safety
+///
+/// `path` must be a NUL-terminated string and `data` must point to `len` readable
+/// doubles (or be NULL if `len` is 0).
+#[no_mangle]
+pub unsafe extern "C" fn dxx_write_with_metadata(
+    path: *const c_char,
+    data: *const c_double,
+    len: usize,
+    scaling: c_int,
+    amount: c_double,
+    channels: u16,
+    sampling_rate: u32,
+) -> DxxStatus {
+    run(|| {
+        let path = path_of(path)?;
+        let src = samples_of(data, len)?;
+        let options = WriteOptions::new().with_scaling(scaling_of(scaling, amount)?);
+        let mut metadata = Metadata::new().with_channels(channels);
+        if sampling_rate != 0 {
+            metadata.sampling_rate = Some(sampling_rate);
+        }
+        dxx::write_file_with_metadata(&path, src, &metadata, &options)?;
+        Ok(())
+    })
+}
+
+unsafe fn samples_of<'a>(data: *const c_double, len: usize) -> Result<&'a [f64], Failure> {
+    if len == 0 {
+        return Ok(&[]);
+    }
+    non_null(data, "data")?;
+    Ok(slice::from_raw_parts(data, len))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::{CStr, CString};
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+    use std::process::Command;
+    use std::ptr;
+
+    use crate::*;
+
+    /// test_dir returns a new directory for the files of one test. It is removed when dropped.
+    fn test_dir() -> tempfile::TempDir {
+        tempfile::Builder::new().prefix("dxx_capi_test_").tempdir().unwrap()
+    }
+
+    fn c_path(dir: &Path, name: &str) -> CString {
+        CString::new(dir.join(name).to_str().unwrap()).unwrap()
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(dxx_last_error()) }.to_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_read_write() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = vec![1., -2., 3., -4.];
+        let path = c_path(dir, "a.DSB");
+        unsafe {
+            assert_eq!(dxx_write(path.as_ptr(), src.as_ptr(), src.len(), DXX_SCALING_RAW, 0.), DXX_OK);
+
+            let mut dtype = -1;
+            assert_eq!(dxx_dtype_from_path(path.as_ptr(), &mut dtype), DXX_OK);
+            assert_eq!(CStr::from_ptr(dxx_dtype_name(dtype)).to_str().unwrap(), "DSB");
+            assert!(dxx_dtype_name(14).is_null());
+
+            let mut buf = [0.; 4];
+            let mut len = 0;
+            assert_eq!(dxx_read(path.as_ptr(), buf.as_mut_ptr(), buf.len(), &mut len), DXX_OK);
+            assert_eq!((len, buf), (4, [1., -2., 3., -4.]));
+            assert_eq!(dxx_read(path.as_ptr(), buf.as_mut_ptr(), 3, &mut len), DXX_ERROR_BUFFER_TOO_SMALL);
+            assert_eq!(len, 4);
+
+            // The shape gives the length, so a small buffer is reported without decoding
+            // the truncated samples.
+            let npy = c_path(dir, "t.npy");
+            assert_eq!(dxx_write(npy.as_ptr(), src.as_ptr(), src.len(), DXX_SCALING_RAW, 0.), DXX_OK);
+            let bytes = fs::read(dir.join("t.npy")).unwrap();
+            fs::write(dir.join("t.npy"), &bytes[..bytes.len() - 1]).unwrap();
+            assert_eq!(dxx_read(npy.as_ptr(), buf.as_mut_ptr(), 3, &mut len), DXX_ERROR_BUFFER_TOO_SMALL);
+            assert_eq!(len, 4);
+            assert_ne!(dxx_read(npy.as_ptr(), buf.as_mut_ptr(), buf.len(), &mut len), DXX_OK);
+
+            let mut data: *mut f64 = ptr::null_mut();
+            assert_eq!(dxx_read_alloc(path.as_ptr(), &mut data, &mut len), DXX_OK);
+            assert_eq!(std::slice::from_raw_parts(data, len), src.as_slice());
+            dxx_free(data, len);
+
+            let path = c_path(dir, "b.DFB");
+            assert_eq!(dxx_write_with_metadata(path.as_ptr(), src.as_ptr(), src.len(), DXX_SCALING_GAIN, 2., 2, 44100), DXX_OK);
+            let mut info = DxxFileInfo { dtype: -1, len: 0, channels: 0, sampling_rate: 0 };
+            assert_eq!(dxx_info(path.as_ptr(), &mut info), DXX_OK);
+            assert_eq!(info, DxxFileInfo { dtype: 4, len: 4, channels: 2, sampling_rate: 44100 });
+            assert_eq!(dxx_read(path.as_ptr(), buf.as_mut_ptr(), buf.len(), &mut len), DXX_OK);
+            assert_eq!(buf, [2., -4., 6., -8.]);
+        }
+    }
+
+    /// The header is compiled by the C compiler (`CC`, or `cc`) and must agree with the
+    /// layout of DxxFileInfo and the constants of this crate.
+    #[test]
+    fn test_header() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let mut program = String::from("#include <stdio.h>\n#include \"dxx.h\"\nint main(void) {\n");
+        let mut want = Vec::new();
+        let mut print = |expr: &str, value: usize| {
+            program.push_str(&format!("    printf(\"%zu\\n\", (size_t)({}));\n", expr));
+            want.push(value.to_string());
+        };
+        print("sizeof(dxx_file_info)", std::mem::size_of::<DxxFileInfo>());
+        print("_Alignof(dxx_file_info)", std::mem::align_of::<DxxFileInfo>());
+        print("offsetof(dxx_file_info, dtype)", std::mem::offset_of!(DxxFileInfo, dtype));
+        print("offsetof(dxx_file_info, len)", std::mem::offset_of!(DxxFileInfo, len));
+        print("offsetof(dxx_file_info, channels)", std::mem::offset_of!(DxxFileInfo, channels));
+        print("offsetof(dxx_file_info, sampling_rate)", std::mem::offset_of!(DxxFileInfo, sampling_rate));
+        let statuses = [
+            ("DXX_OK", DXX_OK as usize),
+            ("DXX_ERROR_INVALID_ARGUMENT", DXX_ERROR_INVALID_ARGUMENT as usize),
+            ("DXX_ERROR_IO", DXX_ERROR_IO as usize),
+            ("DXX_ERROR_UNSUPPORTED_TYPE", DXX_ERROR_UNSUPPORTED_TYPE as usize),
+            ("DXX_ERROR_PARSE", DXX_ERROR_PARSE as usize),
+            ("DXX_ERROR_SIZE_MISMATCH", DXX_ERROR_SIZE_MISMATCH as usize),
+            ("DXX_ERROR_INVALID_FILE", DXX_ERROR_INVALID_FILE as usize),
+            ("DXX_ERROR_CHANNELS", DXX_ERROR_CHANNELS as usize),
+            ("DXX_ERROR_INVALID_SAMPLES", DXX_ERROR_INVALID_SAMPLES as usize),
+            ("DXX_ERROR_BUFFER_TOO_SMALL", DXX_ERROR_BUFFER_TOO_SMALL as usize),
+            ("DXX_ERROR_PANIC", DXX_ERROR_PANIC as usize),
+            ("DXX_ERROR_OTHER", DXX_ERROR_OTHER as usize),
+            ("DXX_SCALING_AUTO", DXX_SCALING_AUTO as usize),
+            ("DXX_SCALING_RAW", DXX_SCALING_RAW as usize),
+            ("DXX_SCALING_PEAK", DXX_SCALING_PEAK as usize),
+            ("DXX_SCALING_GAIN", DXX_SCALING_GAIN as usize),
+            ("DXX_SCALING_RMS", DXX_SCALING_RMS as usize),
+        ];
+        for &(name, value) in statuses.iter() {
+            print(name, value);
+        }
+        for (i, dtype) in DTYPES.iter().enumerate() {
+            print(&format!("DXX_{}", dtype), i);
+        }
+        print("sizeof(dxx_status)", std::mem::size_of::<DxxStatus>());
+        program.push_str("    return 0;\n}\n");
+        // A redeclaration that disagrees with the header fails to compile.
+        let prototypes = [
+            "dxx_status dxx_dtype_from_path(const char *, int *);",
+            "dxx_status dxx_info(const char *, dxx_file_info *);",
+            "dxx_status dxx_read(const char *, double *, size_t, size_t *);",
+            "dxx_status dxx_read_alloc(const char *, double **, size_t *);",
+            "dxx_status dxx_write(const char *, const double *, size_t, int, double);",
+            "dxx_status dxx_write_with_metadata(const char *, const double *, size_t, int, double, uint16_t, uint32_t);",
+        ];
+        for prototype in prototypes.iter() {
+            program.push_str(prototype);
+            program.push('\n');
+        }
+        fs::write(dir.join("header.c"), program).unwrap();
+
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+        let include = concat!(env!("CARGO_MANIFEST_DIR"), "/include");
+        let status = match Command::new(&cc).current_dir(dir).args(["-std=c11", "-Wall", "-Werror", "-I", include, "-o", "header", "header.c"]).status() {
+            Ok(status) => status,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                eprintln!("skipped: no C compiler {}", cc);
+                return;
+            }
+            Err(e) => panic!("{}: {}", cc, e),
+        };
+        assert!(status.success(), "{} failed to compile the header", cc);
+        let out = Command::new(dir.join("header")).output().unwrap();
+        let got: Vec<String> = String::from_utf8(out.stdout).unwrap().lines().map(String::from).collect();
+        assert_eq!(got, want);
+    }
+
+    #[test]
+    fn test_errors() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let mut len = 0;
+        unsafe {
+            assert_eq!(dxx_read(ptr::null(), ptr::null_mut(), 0, &mut len), DXX_ERROR_INVALID_ARGUMENT);
+            assert_eq!(last_error(), "path is NULL");
+
+            let path = c_path(dir, "missing.DSB");
+            assert_eq!(dxx_read(path.as_ptr(), ptr::null_mut(), 0, &mut len), DXX_ERROR_IO);
+            assert!(last_error().contains("missing.DSB"), "{}", last_error());
+
+            let path = c_path(dir, "a.mp3");
+            let mut dtype = -1;
+            assert_eq!(dxx_dtype_from_path(path.as_ptr(), &mut dtype), DXX_ERROR_UNSUPPORTED_TYPE);
+            assert_eq!(dtype, -1);
+
+            let path = c_path(dir, "c.DSB");
+            let src = [0.; 4];
+            assert_eq!(dxx_write(path.as_ptr(), src.as_ptr(), src.len(), 99, 0.), DXX_ERROR_INVALID_ARGUMENT);
+            assert_eq!(last_error(), "unknown scaling: 99");
+        }
+    }
+}
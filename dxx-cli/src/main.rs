@@ -0,0 +1,320 @@
+use anyhow::{Error, Result};
+use std::convert::TryFrom;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+/// dxx converts, inspects and edits .DXX files.
+///
+/// The data type of every file is given by its extension (DSA, DSB, DDA, DXH, wav, npy, ...).
+/// Input files can also be FLAC, Ogg Vorbis or MP3 when built with the `decode` feature.
+#[derive(StructOpt, Debug)]
+#[structopt(name = "dxx")]
+enum Opt {
+    /// Converts a file to another type, e.g. DSB to DDA.
+    Convert {
+        /// Input file.
+        src: PathBuf,
+        /// Output file.
+        dst: PathBuf,
+        #[structopt(flatten)]
+        scaling: ScalingOpt,
+    },
+    /// Prints the type, length and level of files.
+    Info {
+        /// Sampling rate [Hz] used for the duration. Defaults to the one in the metadata.
+        #[structopt(long)]
+        rate: Option<u32>,
+        /// Input files.
+        #[structopt(required = true)]
+        files: Vec<PathBuf>,
+    },
+    /// Prints the samples of a file as text, one frame per line.
+    Cat {
+        /// Input file.
+        file: PathBuf,
+    },
+    /// Writes a range of frames of a file to another file.
+    Slice {
+        /// Input file.
+        src: PathBuf,
+        /// Output file.
+        dst: PathBuf,
+        /// First frame of the range.
+        #[structopt(long, default_value = "0")]
+        start: usize,
+        /// Number of frames of the range. Defaults to the rest of the file.
+        #[structopt(long)]
+        len: Option<usize>,
+        #[structopt(flatten)]
+        scaling: ScalingOpt,
+    },
+    /// Writes files one after another to a file.
+    Concat {
+        /// Output file.
+        #[structopt(short, long)]
+        output: PathBuf,
+        /// Input files. They must have the same channel count and sampling rate.
+        #[structopt(required = true)]
+        files: Vec<PathBuf>,
+        #[structopt(flatten)]
+        scaling: ScalingOpt,
+    },
+}
+
+// ScalingOpt is how the output samples are scaled.
+// convert normalizes like write_file by default; slice and concat keep the samples.
+#[derive(StructOpt, Debug)]
+struct ScalingOpt {
+    /// Scaling of the output: auto, raw, peak, gain or rms.
+    /// auto normalizes to 32767 for the 16-bit types and 10000 for the float types.
+    #[structopt(long, possible_values = &["auto", "raw", "peak", "gain", "rms"])]
+    scaling: Option<String>,
+    /// Value of the peak, gain and rms scalings.
+    #[structopt(long)]
+    amount: Option<f64>,
+}
+
+impl ScalingOpt {
+    fn options(&self, default: dxx::Scaling) -> Result<dxx::WriteOptions> {
+        let amount = || self.amount.ok_or_else(|| Error::msg("--amount is required by this scaling"));
+        let scaling = match self.scaling.as_deref() {
+            None => default,
+            Some("auto") => dxx::Scaling::Auto,
+            Some("raw") => dxx::Scaling::Raw,
+            Some("peak") => dxx::Scaling::Peak(amount()?),
+            Some("gain") => dxx::Scaling::Gain(amount()?),
+            Some("rms") => dxx::Scaling::Rms(amount()?),
+            Some(s) => return Err(Error::msg(format!("unknown scaling: {}", s))),
+        };
+        Ok(dxx::WriteOptions::new().with_scaling(scaling))
+    }
+}
+
+fn main() -> Result<()> {
+    match Opt::from_args() {
+        Opt::Convert { src, dst, scaling } => {
+            let (data, metadata) = dxx::read_audio(&src)?;
+            let metadata = if has_metadata(&src)? { Some(metadata) } else { None };
+            write(&dst, &data, metadata.as_ref(), &scaling.options(dxx::Scaling::Auto)?)?;
+        }
+        Opt::Info { rate, files } => {
+            for (i, file) in files.iter().enumerate() {
+                if i > 0 {
+                    println!();
+                }
+                info(file, rate)?;
+            }
+        }
+        Opt::Cat { file } => cat(&file)?,
+        Opt::Slice { src, dst, start, len, scaling } => {
+            let (data, metadata) = slice(&src, start, len)?;
+            let options = scaling.options(dxx::Scaling::Raw)?;
+            let metadata = if has_metadata(&src)? { Some(metadata) } else { None };
+            write(&dst, &data, metadata.as_ref(), &options)?;
+        }
+        Opt::Concat { output, files, scaling } => {
+            let mut data: Vec<f64> = Vec::new();
+            let mut first: Option<dxx::Metadata> = None;
+            let mut any_metadata = false;
+            for file in files.iter() {
+                let (samples, metadata) = dxx::read_audio(file)?;
+                any_metadata |= has_metadata(file)?;
+                if let Some(first) = &first {
+                    if metadata.channels != first.channels || metadata.sampling_rate != first.sampling_rate {
+                        return Err(Error::msg(format!("{}: channels or sampling rate differ from {}", file.display(), files[0].display())));
+                    }
+                } else {
+                    first = Some(metadata);
+                }
+                data.extend_from_slice(&samples);
+            }
+            let metadata = if any_metadata { first } else { None };
+            write(&output, &data, metadata.as_ref(), &scaling.options(dxx::Scaling::Raw)?)?;
+        }
+    }
+    Ok(())
+}
+
+/// has_metadata reports whether `file` has metadata of its own: a DXH or WAV header,
+/// a sidecar file, the shape of an NPY array or the stream of a decoded format.
+fn has_metadata(file: &Path) -> Result<bool> {
+    Ok(dxx::DType::from_path(file).is_err() || dxx::read_metadata(file)?.is_some())
+}
+
+/// write writes `data` to `dst`, with `metadata` if the input had some.
+/// Without metadata, a plain .DXX file gets no sidecar file.
+fn write(dst: &Path, data: &[f64], metadata: Option<&dxx::Metadata>, options: &dxx::WriteOptions) -> Result<()> {
+    match metadata {
+        Some(metadata) => dxx::write_file_with_metadata(dst, data, metadata, options)?,
+        None => dxx::write_file_with(dst, data, options)?,
+    };
+    Ok(())
+}
+
+/// dxx_metadata returns the metadata of .DXX file without reading its samples.
+/// Without a header, a sidecar file or an NPY shape, a text file has as many channels
+/// as the columns of its first line and a binary file has one.
+fn dxx_metadata(file: &Path, dtype: dxx::DType) -> Result<dxx::Metadata> {
+    if let Some(metadata) = dxx::read_metadata(file)? {
+        return Ok(metadata);
+    }
+    if dtype.is_binary() {
+        return Ok(dxx::Metadata::new());
+    }
+    let mut reader = dxx::DxxReader::open(file)?;
+    if let Some(x) = reader.next() {
+        x.map_err(|e| e.with_path(file))?;
+    }
+    let columns = reader.columns().unwrap_or(1);
+    let channels = u16::try_from(columns).map_err(|_| Error::msg(format!("{}: too many columns: {}", file.display(), columns)))?;
+    Ok(dxx::Metadata::new().with_channels(channels))
+}
+
+/// slice returns `len` frames from `start` of `file` with its metadata.
+/// Only the range of a .DXX file is read; a decoded format is decoded as a whole.
+fn slice(file: &Path, start: usize, len: Option<usize>) -> Result<(Vec<f64>, dxx::Metadata)> {
+    let dtype = match dxx::DType::from_path(file) {
+        Ok(dtype) => dtype,
+        Err(_) => {
+            let (data, metadata) = dxx::read_audio(file)?;
+            let channels = usize::from(metadata.channels);
+            let (begin, end) = frame_range(data.len() / channels, start, len)?;
+            return Ok((data[begin * channels..end * channels].to_vec(), metadata));
+        }
+    };
+    let metadata = dxx_metadata(file, dtype)?;
+    let samples = dxx::len_samples(file)?;
+    metadata.validate(usize::try_from(samples)?)?;
+    let channels = usize::from(metadata.channels);
+    let (begin, end) = frame_range(usize::try_from(samples)? / channels, start, len)?;
+    let data = dxx::read_range(file, (begin * channels) as u64, ((end - begin) * channels) as u64)?;
+    Ok((data, metadata))
+}
+
+/// stream_stats measures the level of .DXX file sample by sample, without keeping the samples.
+fn stream_stats(file: &Path) -> Result<Stats> {
+    let mut reader = dxx::DxxReader::open(file)?;
+    let mut error = None;
+    let stats = Stats::measure(reader.by_ref().map_while(|x| x.map_err(|e| error = Some(e)).ok()));
+    match error {
+        Some(e) => Err(e.with_path(file).into()),
+        None => Ok(stats),
+    }
+}
+
+fn info(file: &Path, rate: Option<u32>) -> Result<()> {
+    // The length of a .DXX file follows from its size or header, and its level is
+    // measured while streaming; a decoded format is decoded as a whole.
+    let (dtype, samples, metadata, stats) = match dxx::DType::from_path(file) {
+        Ok(dtype) => {
+            let metadata = dxx_metadata(file, dtype)?;
+            let samples = usize::try_from(dxx::len_samples(file)?)?;
+            metadata.validate(samples)?;
+            (dtype.to_string(), samples, metadata, stream_stats(file)?)
+        }
+        Err(_) => {
+            let (data, metadata) = dxx::read_audio(file)?;
+            ("decoded".to_string(), data.len(), metadata, Stats::measure(data))
+        }
+    };
+    println!("file: {}", file.display());
+    println!("type: {}", dtype);
+    println!("size: {} bytes", dxx::len_file(file)?);
+    println!("samples: {}", samples);
+    println!("channels: {}", metadata.channels);
+    match rate.or(metadata.sampling_rate) {
+        Some(rate) => {
+            let frames = samples / usize::from(metadata.channels);
+            println!("sampling rate: {} Hz", rate);
+            println!("duration: {} s", frames as f64 / f64::from(rate));
+        }
+        None => println!("duration: unknown (use --rate)"),
+    }
+    println!("peak: {}", stats.peak);
+    println!("rms: {}", stats.rms);
+    println!("dc: {}", stats.dc);
+    println!("nan: {}", stats.nan);
+    Ok(())
+}
+
+fn cat(file: &Path) -> Result<()> {
+    let (data, metadata) = dxx::read_audio(file)?;
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let result = data.chunks(usize::from(metadata.channels)).try_for_each(|frame| {
+        let line: Vec<String> = frame.iter().map(|x| x.to_string()).collect();
+        writeln!(out, "{}", line.join(" "))
+    });
+    match result.and_then(|_| out.flush()) {
+        // A closed pipe such as `dxx cat a.DSB | head` is not an error.
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+/// frame_range returns the begin and the end of `len` frames from `start`
+/// among `frames` frames; `len` defaults to the rest.
+fn frame_range(frames: usize, start: usize, len: Option<usize>) -> Result<(usize, usize)> {
+    let end = match len {
+        Some(len) => start.checked_add(len),
+        None => Some(frames.max(start)),
+    };
+    match end {
+        Some(end) if end <= frames => Ok((start, end)),
+        _ => Err(Error::msg(format!("range out of bounds: only {} frames available", frames))),
+    }
+}
+
+/// Stats is the level of a signal. NaN samples are counted and left out of the rest.
+#[derive(Debug, PartialEq)]
+struct Stats {
+    peak: f64,
+    rms: f64,
+    dc: f64,
+    nan: usize,
+}
+
+impl Stats {
+    fn measure<I: IntoIterator<Item = f64>>(data: I) -> Self {
+        let mut peak: f64 = 0.;
+        let mut sum = 0.;
+        let mut sum_sq = 0.;
+        let mut len = 0;
+        let mut nan = 0;
+        for x in data {
+            len += 1;
+            if x.is_nan() {
+                nan += 1;
+                continue;
+            }
+            peak = peak.max(x.abs());
+            sum += x;
+            sum_sq += x * x;
+        }
+        let n = (len - nan).max(1) as f64;
+        Stats { peak, rms: (sum_sq / n).sqrt(), dc: sum / n, nan }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{frame_range, Stats};
+
+    #[test]
+    fn stats_measure() {
+        let stats = Stats::measure(vec![3., f64::NAN, -1., 1., -3.]);
+        assert_eq!(stats, Stats { peak: 3., rms: 5f64.sqrt(), dc: 0., nan: 1 });
+        assert_eq!(Stats::measure(Vec::new()), Stats { peak: 0., rms: 0., dc: 0., nan: 0 });
+    }
+
+    #[test]
+    fn frame_range_bounds() {
+        assert_eq!(frame_range(10, 2, Some(3)).unwrap(), (2, 5));
+        assert_eq!(frame_range(10, 2, None).unwrap(), (2, 10));
+        assert_eq!(frame_range(10, 10, None).unwrap(), (10, 10));
+        assert!(frame_range(10, 8, Some(3)).is_err());
+        assert!(frame_range(10, 11, None).is_err());
+        assert!(frame_range(10, 1, Some(usize::MAX)).is_err());
+    }
+}
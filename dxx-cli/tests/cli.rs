@@ -0,0 +1,129 @@
+//! End-to-end tests of the dxx command against real files.
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+/// dxx runs the dxx command with `args` in `dir`.
+fn dxx(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dxx")).current_dir(dir).args(args).output().unwrap()
+}
+
+/// dxx_ok runs the dxx command and fails the test if the command fails.
+fn dxx_ok(dir: &Path, args: &[&str]) -> Output {
+    let out = dxx(dir, args);
+    assert!(out.status.success(), "dxx {:?} failed: {}", args, String::from_utf8_lossy(&out.stderr));
+    out
+}
+
+fn raw() -> dxx::WriteOptions {
+    dxx::WriteOptions::new().with_scaling(dxx::Scaling::Raw)
+}
+
+#[test]
+fn convert() {
+    let dir = TempDir::new().unwrap();
+    let src: Vec<f64> = vec![1., -2., 4., -8.];
+    dxx::write_file_with(dir.path().join("a.DDB"), &src, &raw()).unwrap();
+
+    // The default auto scaling normalizes the peak to 32767 for DSB.
+    dxx_ok(dir.path(), &["convert", "a.DDB", "b.DSB"]);
+    assert_eq!(dxx::read_file(dir.path().join("b.DSB")).unwrap(), vec![4096., -8192., 16384., -32767.]);
+    // An input without metadata gives an output without a sidecar file.
+    assert!(!dir.path().join("b.DSB.json").exists());
+
+    dxx_ok(dir.path(), &["convert", "--scaling", "raw", "a.DDB", "c.DDA"]);
+    assert_eq!(dxx::read_file(dir.path().join("c.DDA")).unwrap(), src);
+
+    // The metadata of the input is carried over.
+    let metadata = dxx::Metadata::new().with_sampling_rate(44100).with_channels(2);
+    dxx::write_file_with_metadata(dir.path().join("d.DDB"), &src, &metadata, &raw()).unwrap();
+    dxx_ok(dir.path(), &["convert", "--scaling", "raw", "d.DDB", "e.DFB"]);
+    assert_eq!(dxx::read_file_with_metadata(dir.path().join("e.DFB")).unwrap(), (src, metadata));
+}
+
+#[test]
+fn slice() {
+    let dir = TempDir::new().unwrap();
+    let src: Vec<f64> = (0..10).map(f64::from).collect();
+    dxx::write_file_with(dir.path().join("a.DSB"), &src, &raw()).unwrap();
+
+    dxx_ok(dir.path(), &["slice", "--start", "2", "--len", "3", "a.DSB", "b.DSB"]);
+    assert_eq!(dxx::read_file(dir.path().join("b.DSB")).unwrap(), vec![2., 3., 4.]);
+    assert!(!dir.path().join("b.DSB.json").exists());
+
+    // Frames of a stereo file are sliced as a whole.
+    let stereo = dxx::Metadata::new().with_channels(2);
+    dxx::write_file_with_metadata(dir.path().join("c.DSB"), &src, &stereo, &raw()).unwrap();
+    dxx_ok(dir.path(), &["slice", "--start", "3", "c.DSB", "d.DSB"]);
+    assert_eq!(dxx::read_file_with_metadata(dir.path().join("d.DSB")).unwrap(), (vec![6., 7., 8., 9.], stereo));
+
+    let out = dxx(dir.path(), &["slice", "--start", "8", "--len", "3", "a.DSB", "e.DSB"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("range out of bounds"));
+    assert!(!dir.path().join("e.DSB").exists());
+}
+
+#[test]
+fn concat() {
+    let dir = TempDir::new().unwrap();
+    dxx::write_file_with(dir.path().join("a.DSB"), &[1., 2.], &raw()).unwrap();
+    dxx::write_file_with(dir.path().join("b.DDA"), &[3., 4., 5.], &raw()).unwrap();
+
+    dxx_ok(dir.path(), &["concat", "-o", "c.DDB", "a.DSB", "b.DDA"]);
+    assert_eq!(dxx::read_file(dir.path().join("c.DDB")).unwrap(), vec![1., 2., 3., 4., 5.]);
+    assert!(!dir.path().join("c.DDB.json").exists());
+
+    let metadata = dxx::Metadata::new().with_sampling_rate(16000);
+    dxx::write_file_with_metadata(dir.path().join("d.DSB"), &[6.], &metadata, &raw()).unwrap();
+    dxx::write_file_with_metadata(dir.path().join("e.DSB"), &[7.], &metadata, &raw()).unwrap();
+    dxx_ok(dir.path(), &["concat", "-o", "f.DSB", "d.DSB", "e.DSB"]);
+    assert_eq!(dxx::read_file_with_metadata(dir.path().join("f.DSB")).unwrap(), (vec![6., 7.], metadata));
+}
+
+#[test]
+fn concat_mismatch() {
+    let dir = TempDir::new().unwrap();
+    let mono = dxx::Metadata::new().with_sampling_rate(48000);
+    dxx::write_file_with_metadata(dir.path().join("a.DSB"), &[1., 2.], &mono, &raw()).unwrap();
+    dxx::write_file_with_metadata(dir.path().join("b.DSB"), &[3., 4.], &mono.clone().with_channels(2), &raw()).unwrap();
+    dxx::write_file_with_metadata(dir.path().join("c.DSB"), &[5., 6.], &mono.clone().with_sampling_rate(44100), &raw()).unwrap();
+
+    for other in &["b.DSB", "c.DSB"] {
+        let out = dxx(dir.path(), &["concat", "-o", "d.DSB", "a.DSB", other]);
+        assert!(!out.status.success());
+        assert!(String::from_utf8_lossy(&out.stderr).contains("channels or sampling rate differ from a.DSB"));
+        assert!(!dir.path().join("d.DSB").exists());
+    }
+}
+
+#[test]
+fn info() {
+    let dir = TempDir::new().unwrap();
+    let metadata = dxx::Metadata::new().with_sampling_rate(4).with_channels(2);
+    dxx::write_file_with_metadata(dir.path().join("a.DFB"), &[1., -2., 3., -4.], &metadata, &raw()).unwrap();
+    fs::write(dir.path().join("b.DDA"), "1 -2\n3 -4\n").unwrap();
+
+    let out = dxx_ok(dir.path(), &["info", "a.DFB", "b.DDA"]);
+    let text = String::from_utf8(out.stdout).unwrap();
+    let (a, b) = text.split_once("\n\n").unwrap();
+    for line in &["type: DFB", "size: 16 bytes", "samples: 4", "channels: 2", "duration: 0.5 s", "peak: 4", "dc: -0.5", "nan: 0"] {
+        assert!(a.lines().any(|l| l == *line), "{} in {}", line, a);
+    }
+    // The columns of a text file without a sidecar file are its channels.
+    for line in &["type: DDA", "samples: 4", "channels: 2", "duration: unknown (use --rate)", "peak: 4"] {
+        assert!(b.lines().any(|l| l == *line), "{} in {}", line, b);
+    }
+}
+
+#[test]
+fn slice_text() {
+    let dir = TempDir::new().unwrap();
+    fs::write(dir.path().join("a.DDA"), "1 -2\n3 -4\n5 -6\n").unwrap();
+    dxx_ok(dir.path(), &["slice", "--start", "1", "--len", "1", "a.DDA", "b.DDA"]);
+    assert_eq!(dxx::read_file(dir.path().join("b.DDA")).unwrap(), vec![3., -4.]);
+
+    let out = dxx(dir.path(), &["slice", "--start", "2", "--len", "2", "a.DDA", "c.DDA"]);
+    assert!(String::from_utf8_lossy(&out.stderr).contains("only 3 frames available"));
+}
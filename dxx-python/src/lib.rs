@@ -0,0 +1,243 @@
+//! Python bindings of the dxx library.
+//!
+//! The `dxx` Python module reads and writes .DXX files as NumPy arrays and renders
+//! moving sources like the overlap-add tools. Arrays read from files are handed over
+//! to NumPy without a copy, and C-contiguous arrays are written without a copy.
+use std::convert::TryFrom;
+use std::fs::File;
+use std::path::PathBuf;
+
+use numpy::{PyArrayDyn, PyArrayMethods, PyReadonlyArray1, PyReadonlyArrayDyn, PyUntypedArrayMethods, PyArray1};
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyIOError, PyValueError};
+use pyo3::prelude::*;
+
+use dxx::{Anchor, Movement};
+
+create_exception!(dxx, DxxError, PyException, "DxxError is raised when a .DXX file cannot be read or written.");
+
+/// DType is the data type of a .DXX file.
+#[pyclass(name = "DType", module = "dxx", eq, eq_int, frozen)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PyDType {
+    DSA,
+    DFA,
+    DDA,
+    DSB,
+    DFB,
+    DDB,
+    DCB,
+    DTB,
+    DLB,
+    DUB,
+    DAB,
+    DXH,
+    WAV,
+    NPY,
+}
+
+impl From<dxx::DType> for PyDType {
+    fn from(dtype: dxx::DType) -> Self {
+        match dtype {
+            dxx::DType::DSA => PyDType::DSA,
+            dxx::DType::DFA => PyDType::DFA,
+            dxx::DType::DDA => PyDType::DDA,
+            dxx::DType::DSB => PyDType::DSB,
+            dxx::DType::DFB => PyDType::DFB,
+            dxx::DType::DDB => PyDType::DDB,
+            dxx::DType::DCB => PyDType::DCB,
+            dxx::DType::DTB => PyDType::DTB,
+            dxx::DType::DLB => PyDType::DLB,
+            dxx::DType::DUB => PyDType::DUB,
+            dxx::DType::DAB => PyDType::DAB,
+            dxx::DType::DXH => PyDType::DXH,
+            dxx::DType::WAV => PyDType::WAV,
+            dxx::DType::NPY => PyDType::NPY,
+        }
+    }
+}
+
+impl From<PyDType> for dxx::DType {
+    fn from(dtype: PyDType) -> Self {
+        match dtype {
+            PyDType::DSA => dxx::DType::DSA,
+            PyDType::DFA => dxx::DType::DFA,
+            PyDType::DDA => dxx::DType::DDA,
+            PyDType::DSB => dxx::DType::DSB,
+            PyDType::DFB => dxx::DType::DFB,
+            PyDType::DDB => dxx::DType::DDB,
+            PyDType::DCB => dxx::DType::DCB,
+            PyDType::DTB => dxx::DType::DTB,
+            PyDType::DLB => dxx::DType::DLB,
+            PyDType::DUB => dxx::DType::DUB,
+            PyDType::DAB => dxx::DType::DAB,
+            PyDType::DXH => dxx::DType::DXH,
+            PyDType::WAV => dxx::DType::WAV,
+            PyDType::NPY => dxx::DType::NPY,
+        }
+    }
+}
+
+#[pymethods]
+impl PyDType {
+    /// from_path determines the data type from the extension of the path.
+    #[staticmethod]
+    fn from_path(path: PathBuf) -> PyResult<Self> {
+        let dtype = dxx::DType::from_path(path).map_err(|e| to_py_err(e.into()))?;
+        Ok(dtype.into())
+    }
+
+    /// is_binary reports whether the data type is a binary type, i.e. not one of DSA/DFA/DDA.
+    fn is_binary(&self) -> bool {
+        dxx::DType::from(*self).is_binary()
+    }
+
+    /// byte_width returns the byte width of a sample, or 0 for DXH, WAV and NPY.
+    fn byte_width(&self) -> u32 {
+        dxx::DType::from(*self).byte_width()
+    }
+
+    fn __str__(&self) -> String {
+        dxx::DType::from(*self).to_string()
+    }
+}
+
+/// read_file reads a .DXX file as a float64 array.
+/// A file of more than one channel is returned as a (frames, channels) array.
+/// `dtype` overrides the data type given by the extension.
+#[pyfunction]
+#[pyo3(signature = (path, dtype = None))]
+fn read_file<'py>(py: Python<'py>, path: PathBuf, dtype: Option<PyDType>) -> PyResult<Bound<'py, PyArrayDyn<f64>>> {
+    let (data, channels) = py
+        .detach(|| match dtype {
+            Some(dtype) => {
+                let f = File::open(&path).map_err(|e| dxx::Error::from(e).with_path(&path))?;
+                dxx::read_from(f, dtype.into()).map(|data| (data, 1))
+            }
+            None => dxx::read_file_with_metadata(&path).map(|(data, metadata)| (data, metadata.channels as usize)),
+        })
+        .map_err(to_py_err)?;
+    let frames = data.len() / channels.max(1);
+    let array = PyArray1::from_vec(py, data);
+    if channels > 1 {
+        array.reshape(vec![frames, channels])
+    } else {
+        Ok(array.to_dyn().clone())
+    }
+}
+
+/// write_file writes a float64 array to a .DXX file of the type given by the extension.
+/// A (frames, channels) array is written interleaved and its channel count is kept in
+/// the sidecar metadata, as is `sampling_rate` if given.
+/// `scaling` is one of "auto" (the default of the Rust library), "raw", "peak", "gain"
+/// and "rms"; the last three take their value from `amount`.
+#[pyfunction]
+#[pyo3(signature = (path, data, scaling = "auto", amount = None, sampling_rate = None))]
+fn write_file(py: Python<'_>, path: PathBuf, data: PyReadonlyArrayDyn<'_, f64>, scaling: &str, amount: Option<f64>, sampling_rate: Option<u32>) -> PyResult<()> {
+    let channels = match data.ndim() {
+        1 => 1,
+        2 => data.shape()[1],
+        n => return Err(PyValueError::new_err(format!("data must be 1-D or 2-D, got {}-D", n))),
+    };
+    let channels = u16::try_from(channels).map_err(|_| PyValueError::new_err("too many channels"))?;
+    let options = dxx::WriteOptions::new().with_scaling(parse_scaling(scaling, amount)?);
+    let copy: Vec<f64>;
+    let src = match data.as_slice() {
+        Ok(src) => src,
+        Err(_) => {
+            copy = data.as_array().iter().copied().collect();
+            &copy
+        }
+    };
+    // The array stays borrowed read-only, so the GIL can be released while writing.
+    let result = py.detach(|| {
+        if channels > 1 || sampling_rate.is_some() {
+            let mut metadata = dxx::Metadata::new().with_channels(channels);
+            metadata.sampling_rate = sampling_rate;
+            dxx::write_file_with_metadata(&path, src, &metadata, &options)
+        } else {
+            dxx::write_file_with(&path, src, &options)
+        }
+    });
+    result.map_err(to_py_err)?;
+    Ok(())
+}
+
+/// write_files writes float64 arrays to .DXX files normalized with one common gain,
+/// so that their relative levels are kept. `files` is a list of (path, array) pairs.
+#[pyfunction]
+fn write_files(files: Vec<(PathBuf, PyReadonlyArray1<'_, f64>)>) -> PyResult<()> {
+    let files: Vec<(PathBuf, Vec<f64>)> = files.into_iter().map(|(path, data)| (path, data.as_array().to_vec())).collect();
+    dxx::write_files(&files).map_err(to_py_err)
+}
+
+/// render_moving renders a sound moving around the subject for the ear `lr` ("L" or "R").
+/// The SLTFs are read from `subject/SLTF/SLTF_<angle>_<lr>.DDB`. Angles, `move_width`
+/// and `move_velocity` are counted in steps, of which a full turn has `steps`: 3600 for
+/// the 0.1 degree SLTFs and 360 for the 1 degree ones. `anchor` is one of "start",
+/// "middle" and "end" and tells where `angle` lies on the movement. The result is not
+/// normalized; write the outputs of one condition with write_files to keep their levels.
+#[pyfunction]
+#[pyo3(signature = (subject, sound, move_width, move_velocity, angle, lr, clockwise = true, anchor = "middle", steps = 3600, sampling_rate = dxx::DEFAULT_SAMPLING_RATE))]
+#[allow(clippy::too_many_arguments)]
+fn render_moving<'py>(
+    py: Python<'py>,
+    subject: PathBuf,
+    sound: PyReadonlyArray1<'py, f64>,
+    move_width: u32,
+    move_velocity: u32,
+    angle: u32,
+    lr: &str,
+    clockwise: bool,
+    anchor: &str,
+    steps: u32,
+    sampling_rate: u32,
+) -> PyResult<Bound<'py, PyArray1<f64>>> {
+    let anchor = match anchor {
+        "start" => Anchor::Start,
+        "middle" => Anchor::Middle,
+        "end" => Anchor::End,
+        _ => return Err(PyValueError::new_err(format!("anchor must be one of start, middle and end, got {}", anchor))),
+    };
+    let movement = Movement::new(move_width, move_velocity, angle).with_clockwise(clockwise).with_anchor(anchor).with_steps(steps);
+    // The sound is copied so that the GIL can be released while rendering.
+    let sound = sound.as_array().to_vec();
+    let lr = lr.to_string();
+    let out = py.detach(|| dxx::render_moving(&subject, &sound, sampling_rate, &movement, &lr)).map_err(to_py_err)?;
+    Ok(PyArray1::from_vec(py, out))
+}
+
+fn parse_scaling(scaling: &str, amount: Option<f64>) -> PyResult<dxx::Scaling> {
+    let amount = || amount.ok_or_else(|| PyValueError::new_err(format!("scaling {} needs amount", scaling)));
+    match scaling {
+        "auto" => Ok(dxx::Scaling::Auto),
+        "raw" => Ok(dxx::Scaling::Raw),
+        "peak" => Ok(dxx::Scaling::Peak(amount()?)),
+        "gain" => Ok(dxx::Scaling::Gain(amount()?)),
+        "rms" => Ok(dxx::Scaling::Rms(amount()?)),
+        _ => Err(PyValueError::new_err(format!("scaling must be one of auto, raw, peak, gain and rms, got {}", scaling))),
+    }
+}
+
+/// to_py_err raises I/O failures as OSError, invalid movements as ValueError and
+/// everything else as DxxError.
+fn to_py_err(e: dxx::Error) -> PyErr {
+    match e {
+        dxx::Error::Io { .. } => PyIOError::new_err(e.to_string()),
+        dxx::Error::InvalidMovement(_) => PyValueError::new_err(e.to_string()),
+        e => DxxError::new_err(e.to_string()),
+    }
+}
+
+/// dxx reads and writes .DXX files as NumPy arrays.
+#[pymodule]
+#[pyo3(name = "dxx")]
+fn dxx_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyDType>()?;
+    m.add("DxxError", m.py().get_type::<DxxError>())?;
+    m.add_function(wrap_pyfunction!(read_file, m)?)?;
+    m.add_function(wrap_pyfunction!(write_file, m)?)?;
+    m.add_function(wrap_pyfunction!(write_files, m)?)?;
+    m.add_function(wrap_pyfunction!(render_moving, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,95 @@
+"""Tests of the dxx Python module. Build it with `maturin develop` and run pytest."""
+import numpy as np
+import pytest
+
+import dxx
+
+
+def test_read_write_1d(tmp_path):
+    path = str(tmp_path / "a.DDB")
+    data = np.array([1.0, -2.0, 3.0, -4.0])
+    dxx.write_file(path, data, scaling="raw")
+    got = dxx.read_file(path)
+    assert got.shape == (4,)
+    assert got.dtype == np.float64
+    np.testing.assert_array_equal(got, data)
+    assert not (tmp_path / "a.DDB.json").exists()
+
+
+def test_write_scaling(tmp_path):
+    path = str(tmp_path / "a.DSB")
+    dxx.write_file(path, np.array([0.5, -1.0]))
+    np.testing.assert_array_equal(dxx.read_file(path), [16384.0, -32767.0])
+    dxx.write_file(path, np.array([0.5, -1.0]), scaling="gain", amount=2.0)
+    np.testing.assert_array_equal(dxx.read_file(path), [1.0, -2.0])
+    with pytest.raises(ValueError):
+        dxx.write_file(path, np.array([0.5, -1.0]), scaling="peak")
+
+
+def test_read_write_2d(tmp_path):
+    path = str(tmp_path / "a.DFB")
+    data = np.array([[1.0, -1.0], [2.0, -2.0], [3.0, -3.0]])
+    dxx.write_file(path, data, scaling="raw", sampling_rate=44100)
+    assert (tmp_path / "a.DFB.json").exists()
+    # The frames are interleaved in the file and reshaped to (frames, channels) on reading.
+    np.testing.assert_array_equal(dxx.read_file(path, dtype=dxx.DType.DFB), data.ravel())
+    got = dxx.read_file(path)
+    assert got.shape == (3, 2)
+    np.testing.assert_array_equal(got, data)
+
+
+def test_write_fortran_order(tmp_path):
+    # A Fortran-order or strided array is copied in logical order before it is written.
+    data = np.asfortranarray(np.array([[1.0, -1.0], [2.0, -2.0], [3.0, -3.0]]))
+    assert not data.flags.c_contiguous
+    path = str(tmp_path / "a.DDA")
+    dxx.write_file(path, data, scaling="raw")
+    np.testing.assert_array_equal(dxx.read_file(path), data)
+
+    strided = np.arange(10.0)[::2]
+    path = str(tmp_path / "b.DDB")
+    dxx.write_file(path, strided, scaling="raw")
+    np.testing.assert_array_equal(dxx.read_file(path), [0.0, 2.0, 4.0, 6.0, 8.0])
+
+
+def test_write_files(tmp_path):
+    a, b = str(tmp_path / "a.DSB"), str(tmp_path / "b.DSB")
+    dxx.write_files([(a, np.array([1.0, -2.0])), (b, np.array([0.5]))])
+    np.testing.assert_array_equal(dxx.read_file(a), [16384.0, -32767.0])
+    np.testing.assert_array_equal(dxx.read_file(b), [8192.0])
+
+
+def test_dtype():
+    assert dxx.DType.from_path("a.DSB") == dxx.DType.DSB
+    assert dxx.DType.DSB.byte_width() == 2
+    assert dxx.DType.NPY.byte_width() == 0
+    assert not dxx.DType.DDA.is_binary()
+    assert str(dxx.DType.WAV) == "WAV"
+
+
+def test_errors(tmp_path):
+    with pytest.raises(OSError):
+        dxx.read_file(str(tmp_path / "missing.DSB"))
+    with pytest.raises(dxx.DxxError):
+        dxx.DType.from_path("a.mp3")
+    (tmp_path / "a.DDA").write_text("1\nx\n")
+    with pytest.raises(dxx.DxxError):
+        dxx.read_file(str(tmp_path / "a.DDA"))
+    with pytest.raises(ValueError):
+        dxx.write_file(str(tmp_path / "b.DDB"), np.zeros((2, 2, 2)))
+
+
+def test_render_moving(tmp_path):
+    (tmp_path / "SLTF").mkdir()
+    # The SLTF of angle a is a delay of a samples, two samples long.
+    for angle in range(2):
+        sltf = np.zeros(2)
+        sltf[angle] = 1.0
+        dxx.write_file(str(tmp_path / "SLTF" / "SLTF_{}_L.DDB".format(angle)), sltf, scaling="raw")
+    sound = np.array([1.0, 2.0, 3.0, 4.0, 5.0])
+    got = dxx.render_moving(str(tmp_path), sound, 2, 2, 0, "L", anchor="start", steps=360, sampling_rate=4)
+    np.testing.assert_array_equal(got, [1.0, 2.0, 0.0, 3.0, 4.0])
+    with pytest.raises(ValueError):
+        dxx.render_moving(str(tmp_path), sound, 0, 2, 0, "L")
+    with pytest.raises(ValueError):
+        dxx.render_moving(str(tmp_path), sound, 2, 2, 0, "L", anchor="side")
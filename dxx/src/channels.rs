@@ -0,0 +1,196 @@
+//! Interleaved multichannel data.
+//!
+//! Multichannel data is stored frame by frame: the first sample of every channel,
+//! then the second sample of every channel, and so on. Binary files hold the samples
+//! one after another, text files hold one frame per line with a column per channel.
+//! The number of channels is taken from the metadata of the file.
+use std::convert::TryFrom;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::metadata::{read_file_with_metadata, write_file_with_metadata, Metadata};
+use crate::options::{Scaling, WriteOptions, WriteReport};
+
+/// interleave merges channels of the same length into frame-major samples.
+pub fn interleave<S: AsRef<[f64]>>(channels: &[S]) -> Result<Vec<f64>> {
+    let n = check_channels(channels)?;
+    let want = channels[0].as_ref().len();
+    let mut ret: Vec<f64> = Vec::with_capacity(want * usize::from(n));
+    for i in 0..want {
+        ret.extend(channels.iter().map(|channel| channel.as_ref()[i]));
+    }
+    Ok(ret)
+}
+
+/// deinterleave splits frame-major samples into `channels` channels.
+pub fn deinterleave(src: &[f64], channels: u16) -> Result<Vec<Vec<f64>>> {
+    Metadata::new().with_channels(channels).validate(src.len())?;
+    let n = usize::from(channels);
+    Ok((0..n).map(|c| src.iter().skip(c).step_by(n).copied().collect()).collect())
+}
+
+/// to_frames splits frame-major samples into frames of `channels` samples.
+pub fn to_frames(src: &[f64], channels: u16) -> Result<Vec<Vec<f64>>> {
+    Metadata::new().with_channels(channels).validate(src.len())?;
+    Ok(src.chunks(usize::from(channels)).map(|frame| frame.to_vec()).collect())
+}
+
+/// read_channels reads a multichannel .DXX file as one vector per channel.
+/// The number of channels is taken from the metadata, or from the columns of a text file.
+pub fn read_channels<P: AsRef<Path>>(filename: P) -> Result<(Vec<Vec<f64>>, Metadata)> {
+    let (data, metadata) = read_file_with_metadata(filename)?;
+    Ok((deinterleave(&data, metadata.channels)?, metadata))
+}
+
+/// read_frames reads a multichannel .DXX file as one vector per frame.
+pub fn read_frames<P: AsRef<Path>>(filename: P) -> Result<(Vec<Vec<f64>>, Metadata)> {
+    let (data, metadata) = read_file_with_metadata(filename)?;
+    Ok((to_frames(&data, metadata.channels)?, metadata))
+}
+
+/// write_channels writes channels of the same length to a multichannel .DXX file.
+/// The channel count of `metadata` is replaced with the number of channels, and
+/// a normalizing scaling is applied to all channels with one gain.
+pub fn write_channels<P: AsRef<Path>, S: AsRef<[f64]>>(filename: P, channels: &[S], metadata: &Metadata, options: &WriteOptions) -> Result<WriteReport> {
+    let n = check_channels(channels)?;
+    let data = interleave(channels)?;
+    write_file_with_metadata(filename, &data, &metadata.clone().with_channels(n), options)
+}
+
+/// merge_channels merges .DXX files into one multichannel file.
+/// The channels of every file are appended in order, and the samples are written as they are.
+/// The metadata of the first file is kept. The files must have the same sampling rate;
+/// a file without one takes the sampling rate of the others.
+pub fn merge_channels<P: AsRef<Path>, Q: AsRef<Path>>(srcs: &[P], dst: Q) -> Result<()> {
+    let mut channels: Vec<Vec<f64>> = Vec::with_capacity(srcs.len());
+    let mut metadata: Option<Metadata> = None;
+    let mut sampling_rate: Option<u32> = None;
+    for src in srcs {
+        let (data, m) = read_channels(src)?;
+        match (sampling_rate, m.sampling_rate) {
+            (Some(want), Some(got)) if want != got => {
+                return Err(Error::SamplingRateMismatch { path: src.as_ref().to_path_buf(), want, got });
+            }
+            (None, Some(got)) => sampling_rate = Some(got),
+            _ => {}
+        }
+        channels.extend(data);
+        metadata.get_or_insert(m);
+    }
+    let mut metadata = metadata.unwrap_or_default();
+    metadata.sampling_rate = sampling_rate;
+    let options = WriteOptions::new().with_scaling(Scaling::Raw);
+    write_channels(dst, &channels, &metadata, &options)?;
+    Ok(())
+}
+
+/// split_channels splits a multichannel .DXX file into mono files, one per channel.
+/// The samples are written as they are, and every file gets the metadata of `src`.
+pub fn split_channels<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dsts: &[Q]) -> Result<()> {
+    let (channels, metadata) = read_channels(src)?;
+    if channels.len() != dsts.len() {
+        return Err(Error::ChannelCount { want: channels.len(), got: dsts.len() });
+    }
+    let metadata = metadata.with_channels(1);
+    let options = WriteOptions::new().with_scaling(Scaling::Raw);
+    for (dst, channel) in dsts.iter().zip(channels.iter()) {
+        write_file_with_metadata(dst, channel, &metadata, &options)?;
+    }
+    Ok(())
+}
+
+/// check_channels checks that there is at least one channel and that all channels
+/// have the same length. It returns the number of channels.
+fn check_channels<S: AsRef<[f64]>>(channels: &[S]) -> Result<u16> {
+    let n = u16::try_from(channels.len()).map_err(|_| Error::ChannelCount { want: usize::from(u16::MAX), got: channels.len() })?;
+    if n == 0 {
+        return Err(Error::ChannelMismatch { path: None, len: 0, channels: 0 });
+    }
+    let want = channels[0].as_ref().len();
+    for (channel, src) in channels.iter().enumerate() {
+        let len = src.as_ref().len();
+        if len != want {
+            return Err(Error::ChannelLength { channel, len, want });
+        }
+    }
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::*;
+
+    #[test]
+    fn test_interleave() {
+        let l: Vec<f64> = vec![1., 2., 3.];
+        let r: Vec<f64> = vec![-1., -2., -3.];
+        let data = interleave(&[&l, &r]).unwrap();
+        assert_eq!(data, vec![1., -1., 2., -2., 3., -3.]);
+        assert_eq!(deinterleave(&data, 2).unwrap(), vec![l.clone(), r.clone()]);
+        assert_eq!(to_frames(&data, 2).unwrap(), vec![vec![1., -1.], vec![2., -2.], vec![3., -3.]]);
+
+        assert!(matches!(deinterleave(&data, 4), Err(Error::ChannelMismatch { len: 6, channels: 4, .. })));
+        assert!(matches!(interleave(&[&l, &r[..2]]), Err(Error::ChannelLength { channel: 1, len: 2, want: 3 })));
+        assert!(interleave::<Vec<f64>>(&[]).is_err());
+    }
+
+    #[test]
+    fn test_multichannel_files() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let channels: Vec<Vec<f64>> = vec![vec![1., 2.], vec![3., 4.], vec![-5., 6.]];
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+        let metadata = Metadata::new().with_sampling_rate(48000);
+
+        for name in &["a.DSA", "a.DDA", "a.DFB", "a.DXH"] {
+            let filename = dir.join(name);
+            write_channels(&filename, &channels, &metadata, &options).unwrap();
+            let (got, m) = read_channels(&filename).unwrap();
+            assert_eq!(got, channels, "{}", name);
+            assert_eq!(m, metadata.clone().with_channels(3));
+        }
+        assert_eq!(fs::read_to_string(dir.join("a.DSA")).unwrap(), "1 3 -5\n2 4 6\n");
+
+        // The columns of a text file are channels even without a sidecar file.
+        fs::write(dir.join("b.DDA"), "# L R\n1 -1\n2\t-2\n").unwrap();
+        let (frames, m) = read_frames(dir.join("b.DDA")).unwrap();
+        assert_eq!(frames, vec![vec![1., -1.], vec![2., -2.]]);
+        assert_eq!(m.channels, 2);
+        fs::write(dir.join("c.DDA"), "1 -1\n2\n").unwrap();
+        assert!(matches!(read_channels(dir.join("c.DDA")), Err(Error::ColumnMismatch { line: 2, want: 2, got: 1, .. })));
+        write_metadata(dir.join("b.DDA"), &Metadata::new().with_channels(4)).unwrap();
+        assert!(matches!(read_channels(dir.join("b.DDA")), Err(Error::ChannelCount { want: 4, got: 2 })));
+    }
+
+    #[test]
+    fn test_merge_split_channels() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+        let l: Vec<f64> = vec![1., 2., 3.];
+        let r: Vec<f64> = vec![-1., -2., -3.];
+        write_file_with_metadata(dir.join("L.DSB"), &l, &Metadata::new().with_sampling_rate(44100), &options).unwrap();
+        write_file_with(dir.join("R.DSB"), &r, &options).unwrap();
+
+        merge_channels(&[dir.join("L.DSB"), dir.join("R.DSB")], dir.join("LR.DSB")).unwrap();
+        assert_eq!(read_file(dir.join("LR.DSB")).unwrap(), vec![1., -1., 2., -2., 3., -3.]);
+        assert_eq!(read_metadata(dir.join("LR.DSB")).unwrap(), Some(Metadata::new().with_sampling_rate(44100).with_channels(2)));
+
+        split_channels(dir.join("LR.DSB"), &[dir.join("L2.DDA"), dir.join("R2.DDA")]).unwrap();
+        assert_eq!(read_file(dir.join("L2.DDA")).unwrap(), l);
+        assert_eq!(read_file(dir.join("R2.DDA")).unwrap(), r);
+        assert_eq!(read_metadata(dir.join("R2.DDA")).unwrap().unwrap().sampling_rate, Some(44100));
+        let e = split_channels(dir.join("LR.DSB"), &[dir.join("L2.DDA")]).unwrap_err();
+        assert!(matches!(e, Error::ChannelCount { want: 2, got: 1 }));
+
+        // A file without a sampling rate takes the one of the others, but two rates do not mix.
+        merge_channels(&[dir.join("R.DSB"), dir.join("L.DSB")], dir.join("RL.DSB")).unwrap();
+        assert_eq!(read_metadata(dir.join("RL.DSB")).unwrap().unwrap().sampling_rate, Some(44100));
+        write_file_with_metadata(dir.join("C.DSB"), &l, &Metadata::new().with_sampling_rate(48000), &options).unwrap();
+        let e = merge_channels(&[dir.join("L.DSB"), dir.join("R.DSB"), dir.join("C.DSB")], dir.join("LRC.DSB")).unwrap_err();
+        assert!(matches!(e, Error::SamplingRateMismatch { want: 44100, got: 48000, .. }));
+        assert_eq!(e.to_string(), format!("{}: sampling rate 48000 Hz, want 44100 Hz", dir.join("C.DSB").display()));
+    }
+}
@@ -0,0 +1,84 @@
+//! G.711 mu-law and A-law companding of the DUB/DAB types.
+//!
+//! Both laws map 16-bit linear samples to 8-bit codes. Decoding returns the value at the
+//! middle of the quantization interval of a code, so the codecs are lossy by design.
+
+/// MULAW_BIAS is added to the magnitude before the segment is searched.
+const MULAW_BIAS: i32 = 0x84;
+/// MULAW_CLIP is the largest magnitude that mu-law can encode.
+const MULAW_CLIP: i32 = 32635;
+
+/// encode_mulaw encodes a 16-bit linear sample as a mu-law code.
+pub fn encode_mulaw(x: i16) -> u8 {
+    let x = i32::from(x);
+    let sign = if x < 0 { 0x80 } else { 0 };
+    let magnitude = x.abs().min(MULAW_CLIP) + MULAW_BIAS;
+    let exponent = 8 - (magnitude as u16).leading_zeros() as i32;
+    let mantissa = (magnitude >> (exponent + 3)) & 0x0F;
+    !(sign | (exponent << 4) | mantissa) as u8
+}
+
+/// decode_mulaw decodes a mu-law code to a 16-bit linear sample.
+pub fn decode_mulaw(code: u8) -> i16 {
+    let code = !code;
+    let exponent = i32::from((code >> 4) & 0x07);
+    let mantissa = i32::from(code & 0x0F);
+    let magnitude = (((mantissa << 3) + MULAW_BIAS) << exponent) - MULAW_BIAS;
+    (if code & 0x80 != 0 { -magnitude } else { magnitude }) as i16
+}
+
+/// encode_alaw encodes a 16-bit linear sample as an A-law code.
+pub fn encode_alaw(x: i16) -> u8 {
+    // A-law works on 13-bit samples.
+    let x = i32::from(x) >> 3;
+    let (mask, magnitude) = if x >= 0 { (0xD5, x) } else { (0x55, -x - 1) };
+    let segment = (0..8).find(|&seg| magnitude < (0x20 << seg)).unwrap_or(8);
+    if segment >= 8 {
+        return (0x7F ^ mask) as u8;
+    }
+    let mantissa = if segment < 2 { (magnitude >> 1) & 0x0F } else { (magnitude >> segment) & 0x0F };
+    (((segment << 4) | mantissa) ^ mask) as u8
+}
+
+/// decode_alaw decodes an A-law code to a 16-bit linear sample.
+pub fn decode_alaw(code: u8) -> i16 {
+    let code = i32::from(code ^ 0x55);
+    let segment = (code & 0x70) >> 4;
+    let mut magnitude = (code & 0x0F) << 4;
+    magnitude += if segment == 0 { 8 } else { 0x108 };
+    if segment > 1 {
+        magnitude <<= segment - 1;
+    }
+    (if code & 0x80 != 0 { magnitude } else { -magnitude }) as i16
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_companding() {
+        assert_eq!(encode_mulaw(0), 0xFF);
+        assert_eq!(decode_mulaw(0xFF), 0);
+        assert_eq!(decode_mulaw(encode_mulaw(i16::MAX)), 32124);
+        assert_eq!(decode_mulaw(encode_mulaw(i16::MIN)), -32124);
+        assert_eq!(encode_alaw(0), 0xD5);
+        assert_eq!(decode_alaw(encode_alaw(i16::MAX)), 32256);
+        assert_eq!(decode_alaw(encode_alaw(i16::MIN)), -32256);
+
+        // Every code decodes to a value that encodes back to an equivalent code.
+        for code in 0..=255u8 {
+            let x = decode_mulaw(code);
+            assert_eq!(decode_mulaw(encode_mulaw(x)), x, "mu-law {:#x}", code);
+            let x = decode_alaw(code);
+            assert_eq!(encode_alaw(x), code, "A-law {:#x}", code);
+        }
+
+        // The error grows with the magnitude but stays within a quantization step.
+        for &x in &[1i16, -1, 100, -100, 1000, -1000, 10000, -10000, 30000, -30000] {
+            let tolerance = i32::from(x).abs() / 16 + 16;
+            assert!((i32::from(decode_mulaw(encode_mulaw(x))) - i32::from(x)).abs() <= tolerance, "mu-law {}", x);
+            assert!((i32::from(decode_alaw(encode_alaw(x))) - i32::from(x)).abs() <= tolerance, "A-law {}", x);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+//! Complex-valued .DXX files such as spectra and transfer functions.
+//!
+//! A complex sample is stored as a pair of values: interleaved in the binary types
+//! and as two columns on one line in the text types. The pair is either the real and
+//! imaginary parts or the magnitude and the phase in radians, see `ComplexLayout`.
+use std::path::Path;
+
+use num_complex::Complex;
+
+use crate::error::{Error, Result};
+use crate::options::{Level, Scaling, WriteOptions, WriteReport};
+use crate::{write_file_with, DType, DxxReader};
+
+/// ComplexLayout describes what the two values of a complex sample are.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ComplexLayout {
+    /// ReIm stores the real part and then the imaginary part.
+    #[default]
+    ReIm,
+    /// MagPhase stores the magnitude and then the phase in radians.
+    /// It is meant for the floating-point types, as integers cannot hold the phase.
+    MagPhase,
+}
+
+/// read_complex_file reads complex samples of the re/im layout from .DXX file.
+pub fn read_complex_file<P: AsRef<Path>>(filename: P) -> Result<Vec<Complex<f64>>> {
+    read_complex_file_with(filename, ComplexLayout::ReIm)
+}
+
+/// read_complex_file_with reads complex samples of the specified layout from .DXX file.
+/// A text file has two columns, or a single column in which the two values alternate.
+pub fn read_complex_file_with<P: AsRef<Path>>(filename: P, layout: ComplexLayout) -> Result<Vec<Complex<f64>>> {
+    let filename = filename.as_ref();
+    let mut reader = DxxReader::open(filename)?;
+    let mut data: Vec<f64> = Vec::new();
+    for x in reader.by_ref() {
+        data.push(x.map_err(|e| e.with_path(filename))?);
+    }
+    match reader.columns() {
+        Some(columns) if columns > 2 => return Err(Error::ChannelCount { want: 2, got: columns }),
+        _ => {}
+    }
+    if !data.len().is_multiple_of(2) {
+        return Err(Error::ChannelMismatch { path: None, len: data.len(), channels: 2 });
+    }
+    Ok(data
+        .chunks_exact(2)
+        .map(|pair| match layout {
+            ComplexLayout::ReIm => Complex::new(pair[0], pair[1]),
+            ComplexLayout::MagPhase => Complex::from_polar(pair[0], pair[1]),
+        })
+        .collect())
+}
+
+/// write_complex_file writes complex samples in the re/im layout to .DXX file.
+/// Unlike write_file, the samples are not normalized, so a transfer function keeps its gain.
+pub fn write_complex_file<P: AsRef<Path>>(filename: P, src: &[Complex<f64>]) -> Result<()> {
+    let options = WriteOptions::new().with_scaling(Scaling::Raw);
+    write_complex_file_with(filename, src, ComplexLayout::ReIm, &options)?;
+    Ok(())
+}
+
+/// write_complex_file_with writes complex samples in the specified layout to .DXX file.
+/// The scaling is applied to the complex samples before they are converted to the layout;
+/// its level is measured over the real and imaginary parts.
+/// Non-finite parts are handled before the level is measured; the indices in the report
+/// count the real and imaginary parts, i.e. `2 * i` and `2 * i + 1` for sample `i`.
+pub fn write_complex_file_with<P: AsRef<Path>>(filename: P, src: &[Complex<f64>], layout: ComplexLayout, options: &WriteOptions) -> Result<WriteReport> {
+    let filename = filename.as_ref();
+    let dtype = DType::from_path(filename)?;
+    let data: Vec<f64> = src.iter().flat_map(|z| [z.re, z.im]).collect();
+    let (data, replaced) = options.check_finite(&data)?;
+    let level = Level::measure(&[&data]);
+    options.check_silence(&level)?;
+
+    let data = options.scaling.apply_level(&data, dtype, &level);
+    let data: Vec<f64> = match layout {
+        ComplexLayout::ReIm => data,
+        ComplexLayout::MagPhase => data
+            .chunks_exact(2)
+            .flat_map(|pair| {
+                let (mag, phase) = Complex::new(pair[0], pair[1]).to_polar();
+                [mag, phase]
+            })
+            .collect(),
+    };
+    let options = options.clone().with_scaling(Scaling::Raw).with_channels(2);
+    let mut report = write_file_with(filename, &data, &options)?;
+    report.replaced.extend(replaced);
+    report.replaced.sort_unstable();
+    report.replaced.dedup();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::FRAC_PI_2;
+    use std::fs;
+
+    use num_complex::Complex;
+
+    use crate::*;
+
+    #[test]
+    fn test_complex_file() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<Complex<f64>> = vec![Complex::new(1., 0.), Complex::new(0., -2.), Complex::new(-0.5, 0.25)];
+
+        for name in &["a.DDB", "a.DDA", "a.DXH"] {
+            write_complex_file(dir.join(name), &src).unwrap();
+            assert_eq!(read_complex_file(dir.join(name)).unwrap(), src, "{}", name);
+        }
+        assert_eq!(fs::read_to_string(dir.join("a.DDA")).unwrap(), "1 0\n0 -2\n-0.5 0.25\n");
+
+        let options = WriteOptions::new().with_scaling(Scaling::Gain(2.));
+        write_complex_file_with(dir.join("b.DDA"), &src, ComplexLayout::MagPhase, &options).unwrap();
+        let text = fs::read_to_string(dir.join("b.DDA")).unwrap();
+        assert_eq!(text.lines().nth(1).unwrap(), format!("4 {}", -FRAC_PI_2));
+        let got = read_complex_file_with(dir.join("b.DDA"), ComplexLayout::MagPhase).unwrap();
+        for (z, w) in src.iter().zip(got.iter()) {
+            assert!((z * 2. - w).norm() < 1e-12, "{} {}", z, w);
+        }
+
+        fs::write(dir.join("c.DDA"), "1\n2\n3\n").unwrap();
+        assert!(matches!(read_complex_file(dir.join("c.DDA")), Err(Error::ChannelMismatch { len: 3, channels: 2, .. })));
+        fs::write(dir.join("c.DDA"), "1 2 3\n").unwrap();
+        assert!(matches!(read_complex_file(dir.join("c.DDA")), Err(Error::ChannelCount { want: 2, got: 3 })));
+
+        // A non-finite part is replaced before the peak is measured, so it does not
+        // turn the other samples into zeros.
+        let bad = vec![Complex::new(1., f64::NAN), Complex::new(-0.5, 0.25)];
+        let options = WriteOptions::new()
+            .with_scaling(Scaling::Peak(2.))
+            .with_non_finite(NonFinitePolicy::ReplaceWithZero);
+        let report = write_complex_file_with(dir.join("d.DDA"), &bad, ComplexLayout::ReIm, &options).unwrap();
+        assert_eq!(report.replaced, vec![1]);
+        let got = read_complex_file(dir.join("d.DDA")).unwrap();
+        assert_eq!(got, vec![Complex::new(2., 0.), Complex::new(-1., 0.5)]);
+        let options = WriteOptions::new().with_scaling(Scaling::Peak(2.));
+        let e = write_complex_file_with(dir.join("d.DDA"), &bad, ComplexLayout::ReIm, &options).unwrap_err();
+        assert!(matches!(e, Error::NonFinite { index: 1, .. }));
+    }
+}
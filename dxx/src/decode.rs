@@ -0,0 +1,166 @@
+//! Reading of common audio files next to the DXX types.
+//!
+//! `read_audio` reads every DXX type, DXH and WAV as usual. With the `decode` feature,
+//! it also decodes FLAC, Ogg Vorbis and MP3 files.
+use std::path::Path;
+
+use crate::error::Result;
+use crate::metadata::{read_file_with_metadata, Metadata};
+use crate::{DType, DTypeError};
+
+/// read_audio reads an audio file and its metadata.
+/// A file with a DXX suffix (DXH and WAV included) is read like read_file_with_metadata.
+/// Any other file is decoded if the `decode` feature is enabled, and fails with
+/// `Error::UnsupportedType` otherwise. Decoded samples are interleaved, lie in [-1, 1]
+/// and come with the sampling rate and the channel count of the file.
+pub fn read_audio<P: AsRef<Path>>(filename: P) -> Result<(Vec<f64>, Metadata)> {
+    let filename = filename.as_ref();
+    match DType::from_path(filename) {
+        Ok(_) => read_file_with_metadata(filename),
+        Err(e) => decode_file(filename, e),
+    }
+}
+
+#[cfg(not(feature = "decode"))]
+fn decode_file(_: &Path, e: DTypeError) -> Result<(Vec<f64>, Metadata)> {
+    Err(e.into())
+}
+
+#[cfg(feature = "decode")]
+fn decode_file(filename: &Path, _: DTypeError) -> Result<(Vec<f64>, Metadata)> {
+    use std::convert::TryFrom;
+    use std::fs::File;
+    use std::io::ErrorKind;
+
+    use symphonia::core::audio::SampleBuffer;
+    use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+    use symphonia::core::errors::Error as DecodeError;
+    use symphonia::core::formats::FormatOptions;
+    use symphonia::core::io::MediaSourceStream;
+    use symphonia::core::meta::MetadataOptions;
+    use symphonia::core::probe::Hint;
+
+    use crate::error::Error;
+
+    let fail = |message: String| Error::Decode { path: filename.to_path_buf(), message };
+    let f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let src = MediaSourceStream::new(Box::new(f), Default::default());
+    let mut hint = Hint::new();
+    if let Some(extension) = filename.extension().and_then(|s| s.to_str()) {
+        hint.with_extension(extension);
+    }
+    let probed = symphonia::default::get_probe()
+        .format(&hint, src, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| fail(e.to_string()))?;
+    let mut format = probed.format;
+    let track = format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| fail("no audio track".to_string()))?;
+    let track_id = track.id;
+    let mut metadata = Metadata::new();
+    metadata.sampling_rate = track.codec_params.sample_rate;
+    if let Some(channels) = track.codec_params.channels {
+        metadata.channels = u16::try_from(channels.count()).map_err(|_| fail("too many channels".to_string()))?;
+    }
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| fail(e.to_string()))?;
+
+    let mut data: Vec<f64> = Vec::new();
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(DecodeError::IoError(ref e)) if e.kind() == ErrorKind::UnexpectedEof => break,
+            // A chained Ogg stream changes its tracks; only the first stream is read.
+            Err(DecodeError::ResetRequired) => break,
+            Err(e) => return Err(fail(e.to_string())),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                let mut buf: SampleBuffer<f64> = SampleBuffer::new(decoded.capacity() as u64, *decoded.spec());
+                buf.copy_interleaved_ref(decoded);
+                data.extend_from_slice(buf.samples());
+            }
+            // A corrupt packet is skipped as players do.
+            Err(DecodeError::DecodeError(_)) => continue,
+            Err(e) => return Err(fail(e.to_string())),
+        }
+    }
+    metadata.validate(data.len())?;
+    Ok((data, metadata))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::*;
+
+    #[test]
+    fn test_read_audio() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = vec![1., -2., 3., -4.];
+        let metadata = Metadata::new().with_sampling_rate(44100).with_channels(2);
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+        for name in &["a.DSB", "a.wav"] {
+            write_file_with_metadata(dir.join(name), &src, &metadata, &options).unwrap();
+            assert_eq!(read_audio(dir.join(name)).unwrap(), (src.clone(), metadata.clone()), "{}", name);
+        }
+
+        fs::write(dir.join("b.flac"), b"not a FLAC file").unwrap();
+        let e = read_audio(dir.join("b.flac")).unwrap_err();
+        if cfg!(feature = "decode") {
+            assert!(matches!(e, Error::Decode { .. }), "{:?}", e);
+        } else {
+            assert!(matches!(e, Error::UnsupportedType(DTypeError::InvalidFileSuffix(_))), "{:?}", e);
+        }
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn test_decode_flac() {
+        // stereo.flac holds one verbatim 16-bit frame of 16 samples at 44.1 kHz:
+        // the left channel rises from -8192 in steps of 1024, the right one is its negation.
+        let filename = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/stereo.flac");
+        let (data, metadata) = read_audio(filename).unwrap();
+        assert_eq!(metadata.sampling_rate, Some(44100));
+        assert_eq!(metadata.channels, 2);
+        let want: Vec<f64> = (0..16)
+            .flat_map(|i| {
+                let x = f64::from((i - 8) * 1024) / 32768.;
+                vec![x, -x]
+            })
+            .collect();
+        assert_eq!(data, want);
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn test_decode_vorbis() {
+        // stereo.ogg holds five short-block Vorbis packets at 22.05 kHz whose floors are unused,
+        // so every channel is silent; the overlap of the five blocks yields 4 * 128 samples.
+        let filename = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/stereo.ogg");
+        let (data, metadata) = read_audio(filename).unwrap();
+        assert_eq!(metadata.sampling_rate, Some(22050));
+        assert_eq!(metadata.channels, 2);
+        assert_eq!(data, vec![0.; 2 * 4 * 128]);
+    }
+
+    #[cfg(feature = "decode")]
+    #[test]
+    fn test_decode_mp3() {
+        // mono.mp3 holds four MPEG-1 Layer III frames at 48 kHz and 32 kbit/s whose side
+        // information and main data are all zero, i.e. 4 * 1152 samples of silence.
+        let filename = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/mono.mp3");
+        let (data, metadata) = read_audio(filename).unwrap();
+        assert_eq!(metadata.sampling_rate, Some(48000));
+        assert_eq!(metadata.channels, 1);
+        assert_eq!(data, vec![0.; 4 * 1152]);
+    }
+}
@@ -0,0 +1,440 @@
+//! The self-describing .DXH container.
+//!
+//! A DXH file is a header followed by the samples of one of the binary types.
+//! All header fields are little-endian:
+//!
+//! | size | field |
+//! |------|-------|
+//! | 4    | magic `DXH\0` |
+//! | 2    | version |
+//! | 1    | byte order of the payload (0: little, 1: big) |
+//! | 1    | length n of the payload type name |
+//! | n    | payload type name, e.g. `DSB` |
+//! | 4    | sampling rate in Hz, 0 if unknown |
+//! | 2    | number of interleaved channels |
+//! | 4    | number of key/value pairs |
+//! | ...  | each pair as a length-prefixed (u32) UTF-8 key and value |
+//! | ...  | zero padding up to a multiple of 8 bytes |
+//!
+//! The padding keeps the payload aligned, so a mapped DXH file can be viewed without copying.
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::endian::Endian;
+use crate::error::{Error, Result};
+use crate::metadata::{read_metadata, write_metadata, Metadata};
+use crate::options::{Level, ReadOptions, Scaling, WriteOptions, WriteReport};
+use crate::stream::DxxWriter;
+use crate::npy::read_npy_header;
+use crate::wav::{read_wav_spec, write_wav, WavSpec};
+use crate::{len_file, read_file_with, write_file_with, DType, DxxReader};
+
+/// DXH_MAGIC is the first four bytes of a DXH file.
+pub const DXH_MAGIC: [u8; 4] = *b"DXH\0";
+/// DXH_VERSION is the version of the header this library writes.
+pub const DXH_VERSION: u16 = 1;
+/// DXH_ALIGN is the alignment of the payload.
+const DXH_ALIGN: usize = 8;
+
+const KEY_UNITS: &str = "units";
+const KEY_CALIBRATION_GAIN: &str = "calibration_gain";
+const KEY_CREATOR: &str = "creator";
+const TAG_PREFIX: &str = "tag.";
+
+/// DxhHeader is the header of a DXH file.
+/// The sampling rate, the channel count and the key/value block are kept in `metadata`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DxhHeader {
+    /// dtype is the type of the payload. It is one of the binary types.
+    pub dtype: DType,
+    /// endian is the byte order of the payload.
+    pub endian: Endian,
+    pub metadata: Metadata,
+}
+
+impl DxhHeader {
+    /// new returns a little-endian header of the specified payload type without metadata.
+    pub fn new(dtype: DType) -> Self {
+        DxhHeader { dtype, endian: Endian::default(), metadata: Metadata::default() }
+    }
+
+    /// with_endian returns the header with the specified byte order.
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// with_metadata returns the header with the specified metadata.
+    pub fn with_metadata(mut self, metadata: Metadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// read reads a header from `src` and returns it with its length in bytes,
+    /// padding included. `src` is left at the first byte of the payload.
+    pub fn read<R: Read>(src: &mut R) -> Result<(Self, u64)> {
+        let mut src = Counter { inner: src, count: 0 };
+        let mut magic = [0u8; 4];
+        src.read_exact(&mut magic).map_err(truncated)?;
+        if magic != DXH_MAGIC {
+            return Err(invalid("bad magic number"));
+        }
+        let version = src.read_u16::<LittleEndian>().map_err(truncated)?;
+        if version != DXH_VERSION {
+            return Err(invalid(&format!("unsupported version {}", version)));
+        }
+        let endian = match src.read_u8().map_err(truncated)? {
+            0 => Endian::Little,
+            1 => Endian::Big,
+            b => return Err(invalid(&format!("unknown byte order {}", b))),
+        };
+        let n = src.read_u8().map_err(truncated)?;
+        let name = read_string(&mut src, u32::from(n))?;
+        let dtype: DType = name.parse().map_err(|_| invalid(&format!("unknown payload type {:?}", name)))?;
+        check_payload(dtype)?;
+
+        let mut metadata = Metadata::new();
+        let rate = src.read_u32::<LittleEndian>().map_err(truncated)?;
+        metadata.sampling_rate = if rate == 0 { None } else { Some(rate) };
+        metadata.channels = src.read_u16::<LittleEndian>().map_err(truncated)?;
+        if metadata.channels == 0 {
+            return Err(invalid("zero channels"));
+        }
+        let pairs = src.read_u32::<LittleEndian>().map_err(truncated)?;
+        for _ in 0..pairs {
+            let n = src.read_u32::<LittleEndian>().map_err(truncated)?;
+            let key = read_string(&mut src, n)?;
+            let n = src.read_u32::<LittleEndian>().map_err(truncated)?;
+            let value = read_string(&mut src, n)?;
+            match key.as_str() {
+                KEY_UNITS => metadata.units = Some(value),
+                KEY_CALIBRATION_GAIN => {
+                    let gain = value.parse().map_err(|_| invalid(&format!("invalid calibration gain {:?}", value)))?;
+                    metadata.calibration_gain = Some(gain);
+                }
+                KEY_CREATOR => metadata.creator = Some(value),
+                // Unknown keys are skipped so that newer files stay readable.
+                _ => {
+                    if let Some(tag) = key.strip_prefix(TAG_PREFIX) {
+                        metadata.tags.insert(tag.to_string(), value);
+                    }
+                }
+            }
+        }
+
+        let padding = padding(src.count as usize);
+        io::copy(&mut (&mut src).take(padding as u64), &mut io::sink())?;
+        if !src.count.is_multiple_of(DXH_ALIGN as u64) {
+            return Err(invalid("truncated"));
+        }
+        Ok((DxhHeader { dtype, endian, metadata }, src.count))
+    }
+
+    /// write writes the header to `dst` and returns its length in bytes, padding included.
+    pub fn write<W: Write>(&self, dst: &mut W) -> Result<u64> {
+        check_payload(self.dtype)?;
+        if self.metadata.channels == 0 {
+            return Err(Error::ChannelMismatch { path: None, len: 0, channels: 0 });
+        }
+        let mut buf: Vec<u8> = Vec::new();
+        buf.extend_from_slice(&DXH_MAGIC);
+        buf.write_u16::<LittleEndian>(DXH_VERSION)?;
+        buf.write_u8(match self.endian {
+            Endian::Little => 0,
+            Endian::Big => 1,
+        })?;
+        let name = self.dtype.to_string();
+        buf.write_u8(name.len() as u8)?;
+        buf.extend_from_slice(name.as_bytes());
+        buf.write_u32::<LittleEndian>(self.metadata.sampling_rate.unwrap_or(0))?;
+        buf.write_u16::<LittleEndian>(self.metadata.channels)?;
+
+        let mut pairs: Vec<(String, String)> = Vec::new();
+        if let Some(ref units) = self.metadata.units {
+            pairs.push((KEY_UNITS.to_string(), units.clone()));
+        }
+        if let Some(gain) = self.metadata.calibration_gain {
+            pairs.push((KEY_CALIBRATION_GAIN.to_string(), gain.to_string()));
+        }
+        if let Some(ref creator) = self.metadata.creator {
+            pairs.push((KEY_CREATOR.to_string(), creator.clone()));
+        }
+        for (key, value) in self.metadata.tags.iter() {
+            pairs.push((format!("{}{}", TAG_PREFIX, key), value.clone()));
+        }
+        buf.write_u32::<LittleEndian>(pairs.len() as u32)?;
+        for (key, value) in pairs.iter() {
+            for s in &[key, value] {
+                buf.write_u32::<LittleEndian>(s.len() as u32)?;
+                buf.extend_from_slice(s.as_bytes());
+            }
+        }
+        buf.resize(buf.len() + padding(buf.len()), 0);
+
+        dst.write_all(&buf)?;
+        Ok(buf.len() as u64)
+    }
+}
+
+/// read_dxh_header reads the header of the specified DXH file.
+pub fn read_dxh_header<P: AsRef<Path>>(filename: P) -> Result<DxhHeader> {
+    let filename = filename.as_ref();
+    let f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let (header, _) = DxhHeader::read(&mut BufReader::new(f)).map_err(|e| e.with_path(filename))?;
+    Ok(header)
+}
+
+/// read_dxh reads the samples and the header of the specified DXH file.
+/// The number of samples has to fit the channel count of the header.
+pub fn read_dxh<P: AsRef<Path>>(filename: P) -> Result<(Vec<f64>, DxhHeader)> {
+    let filename = filename.as_ref();
+    let mut reader = DxxReader::open(filename)?;
+    let header = reader.header().cloned().ok_or(Error::TypeMismatch { want: DType::DXH, got: reader.dtype() })?;
+    let mut ret: Vec<f64> = Vec::new();
+    for x in reader.by_ref() {
+        ret.push(x.map_err(|e| e.with_path(filename))?);
+    }
+    header.metadata.validate(ret.len())?;
+    Ok((ret, header))
+}
+
+/// write_dxh writes samples to the specified DXH file with the specified header.
+/// The samples are scaled for the payload type of the header, and the byte order
+/// of the header takes precedence over the one of the options.
+pub fn write_dxh<P: AsRef<Path>>(filename: P, src: &[f64], header: &DxhHeader, options: &WriteOptions) -> Result<WriteReport> {
+    let filename = filename.as_ref();
+    header.metadata.validate(src.len())?;
+    check_payload(header.dtype)?;
+    let (src, replaced) = options.check_finite(src)?;
+    let level = Level::measure(&[&src]);
+    options.check_silence(&level)?;
+
+    let samples = options.scaling.apply(&src, header.dtype);
+    let f = File::create(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let mut writer = DxxWriter::new(f, DType::DXH)
+        .with_header(header.clone())
+        .with_quantization(options.quantization);
+    writer.write_samples(&samples).map_err(|e| e.with_path(filename))?;
+    let report = WriteReport { clipped: writer.clipped(), replaced, silent: level.is_silent() };
+    writer.finish().map_err(|e| e.with_path(filename))?;
+    Ok(report)
+}
+
+/// to_dxh converts a little-endian legacy .DXX file to a DXH file without loss.
+/// Binary payloads are copied byte for byte; text data is stored as the binary type
+/// of the same sample type (DSA as DSB, DFA as DFB and DDA as DDB).
+/// The sidecar metadata of `src`, if any, is moved into the header.
+/// A WAV or NPY file keeps its payload type and channel count.
+pub fn to_dxh<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    let (src, dst) = (src.as_ref(), dst.as_ref());
+    let dtype = DType::from_path(src)?;
+    if dtype == DType::DXH {
+        return Err(Error::TypeMismatch { want: binary_of(dtype), got: dtype });
+    }
+    let metadata = read_metadata(src)?.unwrap_or_default();
+    let payload = match dtype {
+        DType::WAV => read_wav_spec(src)?.dtype,
+        DType::NPY => read_npy_header(src)?.dtype,
+        _ => binary_of(dtype),
+    };
+    let header = DxhHeader::new(payload).with_metadata(metadata);
+
+    if !dtype.is_binary() || dtype.is_container() {
+        let (data, _) = read_file_with(src, &ReadOptions::default())?;
+        write_dxh(dst, &data, &header, &WriteOptions::new().with_scaling(Scaling::Raw))?;
+        return Ok(());
+    }
+    let len = len_file(src)?;
+    if !len.is_multiple_of(u64::from(dtype.byte_width())) {
+        return Err(Error::SizeMismatch { path: None, len, dtype }.with_path(src));
+    }
+    header.metadata.validate((len / u64::from(dtype.byte_width())) as usize)?;
+    let mut input = File::open(src).map_err(|e| Error::from(e).with_path(src))?;
+    let f = File::create(dst).map_err(|e| Error::from(e).with_path(dst))?;
+    let mut output = BufWriter::new(f);
+    header.write(&mut output).map_err(|e| e.with_path(dst))?;
+    io::copy(&mut input, &mut output).map_err(|e| Error::from(e).with_path(dst))?;
+    output.flush().map_err(|e| Error::from(e).with_path(dst))?;
+    Ok(())
+}
+
+/// from_dxh converts a DXH file to a little-endian legacy .DXX file.
+/// The samples are written as they are, so the conversion is lossless whenever `dst`
+/// can hold the payload type (e.g. a DSB payload written as DSB, DSA, DFB or DDB).
+/// The metadata of the header is written to the sidecar file of `dst` unless it is empty.
+/// A WAV file gets the payload type of the header, which makes it lossless as well.
+pub fn from_dxh<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    let dst = dst.as_ref();
+    let dtype = DType::from_path(dst)?;
+    if dtype == DType::DXH {
+        return Err(Error::TypeMismatch { want: DType::DDB, got: dtype });
+    }
+    let (data, header) = read_dxh(src)?;
+    let options = WriteOptions::new().with_scaling(Scaling::Raw);
+    if dtype == DType::WAV {
+        write_wav(dst, &data, &WavSpec::from_metadata(header.dtype, &header.metadata), &options)?;
+        return Ok(());
+    }
+    write_file_with(dst, &data, &options)?;
+    if header.metadata != Metadata::default() {
+        write_metadata(dst, &header.metadata)?;
+    }
+    Ok(())
+}
+
+/// binary_of returns the binary type that stores the same samples as `dtype`.
+fn binary_of(dtype: DType) -> DType {
+    match dtype {
+        DType::DSA => DType::DSB,
+        DType::DFA => DType::DFB,
+        DType::DDA => DType::DDB,
+        _ => dtype,
+    }
+}
+
+/// check_payload checks that `dtype` can be the payload of a DXH file.
+pub(crate) fn check_payload(dtype: DType) -> Result<()> {
+    if !dtype.is_binary() || dtype.is_container() {
+        return Err(invalid(&format!("{} cannot be a payload type", dtype)));
+    }
+    Ok(())
+}
+
+fn padding(len: usize) -> usize {
+    (DXH_ALIGN - len % DXH_ALIGN) % DXH_ALIGN
+}
+
+fn read_string<R: Read>(src: &mut R, len: u32) -> Result<String> {
+    let mut buf: Vec<u8> = Vec::new();
+    src.take(u64::from(len)).read_to_end(&mut buf)?;
+    if buf.len() != len as usize {
+        return Err(invalid("truncated"));
+    }
+    String::from_utf8(buf).map_err(|_| invalid("key/value is not UTF-8"))
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidHeader(reason.to_string())
+}
+
+fn truncated(e: io::Error) -> Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        return invalid("truncated");
+    }
+    e.into()
+}
+
+/// Counter counts the bytes read through it.
+pub(crate) struct Counter<'a, R: Read> {
+    pub(crate) inner: &'a mut R,
+    pub(crate) count: u64,
+}
+
+impl<R: Read> Read for Counter<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::*;
+
+    #[test]
+    fn test_dxh_header() {
+        let metadata = Metadata::new()
+            .with_sampling_rate(44100)
+            .with_channels(2)
+            .with_units("Pa")
+            .with_calibration_gain(0.1)
+            .with_creator("dxx")
+            .with_tag("units", "not the units");
+        let header = DxhHeader::new(DType::DFB).with_endian(Endian::Big).with_metadata(metadata);
+        let mut buf: Vec<u8> = Vec::new();
+        let len = header.write(&mut buf).unwrap();
+        assert_eq!(len as usize, buf.len());
+        assert_eq!(len % 8, 0);
+        assert_eq!(&buf[..4], b"DXH\0");
+
+        buf.extend_from_slice(&[1, 2, 3]);
+        let mut src = buf.as_slice();
+        let (got, got_len) = DxhHeader::read(&mut src).unwrap();
+        assert_eq!(got, header);
+        assert_eq!(got_len, len);
+        assert_eq!(src, &[1, 2, 3]);
+
+        assert!(matches!(DxhHeader::read(&mut &buf[..10]), Err(Error::InvalidHeader(_))));
+        assert!(matches!(DxhHeader::read(&mut &b"RIFF...."[..]), Err(Error::InvalidHeader(_))));
+        assert!(DxhHeader::new(DType::DDA).write(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_dxh_file() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = vec![5., -2., 4., -3.];
+
+        // write_file stores f64 samples with the usual DDB normalization.
+        assert_eq!(DType::from_filename("a.dxh").unwrap(), DType::DXH);
+        write_file(dir.join("a.DXH"), src.clone()).unwrap();
+        assert_eq!(read_file(dir.join("a.DXH")).unwrap(), vec![10000., -4000., 8000., -6000.]);
+        assert_eq!(read_dxh_header(dir.join("a.DXH")).unwrap(), DxhHeader::new(DType::DDB));
+        assert_eq!(read_range(dir.join("a.DXH"), 1, 2).unwrap(), vec![-4000., 8000.]);
+
+        let header = DxhHeader::new(DType::DSB).with_endian(Endian::Big).with_metadata(Metadata::new().with_sampling_rate(48000));
+        write_dxh(dir.join("b.DXH"), &src, &header, &WriteOptions::new().with_scaling(Scaling::Raw)).unwrap();
+        let (data, got) = read_dxh(dir.join("b.DXH")).unwrap();
+        assert_eq!(data, src);
+        assert_eq!(got, header);
+        let reader = DxxReader::open(dir.join("b.DXH")).unwrap();
+        assert_eq!(reader.dtype(), DType::DSB);
+        assert_eq!(reader.header(), Some(&header));
+    }
+
+    #[test]
+    fn test_dxh_legacy_conversion() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = vec![0.5, -2., 300., 0.001];
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+        let metadata = Metadata::new().with_sampling_rate(48000).with_tag("take", "3");
+
+        for &(name, dtype) in &[
+            ("a.DSB", DType::DSB),
+            ("a.DFB", DType::DFB),
+            ("a.DDB", DType::DDB),
+            ("a.DSA", DType::DSB),
+            ("a.DFA", DType::DFB),
+            ("a.DDA", DType::DDB),
+        ] {
+            let legacy = dir.join(name);
+            write_file_with(&legacy, &src, &options).unwrap();
+            write_metadata(&legacy, &metadata).unwrap();
+            let want = read_file_as::<f64, _>(&legacy).unwrap();
+
+            let dxh = dir.join(format!("{}.DXH", name));
+            to_dxh(&legacy, &dxh).unwrap();
+            let (data, header) = read_dxh(&dxh).unwrap();
+            assert_eq!(header.dtype, dtype, "{}", name);
+            assert_eq!(header.metadata, metadata);
+            // DFA is parsed as f64, so compare at the precision of the payload.
+            let data: Vec<f32> = data.into_iter().map(|x| x as f32).collect();
+            let want: Vec<f32> = want.into_iter().map(|x| x as f32).collect();
+            assert_eq!(data, want, "{}", name);
+
+            let back = dir.join(format!("b{}", name));
+            from_dxh(&dxh, &back).unwrap();
+            assert_eq!(fs::read(&back).unwrap(), fs::read(&legacy).unwrap(), "{}", name);
+            assert_eq!(read_metadata(&back).unwrap(), Some(metadata.clone()));
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Byte order of the binary .DXX types.
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::companding::{decode_alaw, decode_mulaw};
+use crate::error::{Error, Result};
+use crate::DType;
+
+/// GUESS_MAX_BYTES is the number of bytes guess_endian_file looks at.
+const GUESS_MAX_BYTES: u64 = 1 << 20;
+
+/// Endian is the byte order of DSB/DFB/DDB data.
+/// DXX files are little-endian unless they come from old big-endian machines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+/// EndianGuess is the result of guess_endian.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EndianGuess {
+    pub endian: Endian,
+    /// confidence is in [0, 1]. 0 means that both byte orders look equally plausible.
+    pub confidence: f64,
+}
+
+pub(crate) fn decode_sample(buf: &[u8], dtype: DType, endian: Endian) -> f64 {
+    match (dtype, endian) {
+        (DType::DSB, Endian::Little) => f64::from(LittleEndian::read_i16(buf)),
+        (DType::DSB, Endian::Big) => f64::from(BigEndian::read_i16(buf)),
+        (DType::DFB, Endian::Little) => f64::from(LittleEndian::read_f32(buf)),
+        (DType::DFB, Endian::Big) => f64::from(BigEndian::read_f32(buf)),
+        (DType::DTB, Endian::Little) => f64::from(LittleEndian::read_i24(buf)),
+        (DType::DTB, Endian::Big) => f64::from(BigEndian::read_i24(buf)),
+        (DType::DLB, Endian::Little) => f64::from(LittleEndian::read_i32(buf)),
+        (DType::DLB, Endian::Big) => f64::from(BigEndian::read_i32(buf)),
+        // The byte order does not matter for the single-byte types.
+        (DType::DCB, _) => f64::from(buf[0] as i8),
+        (DType::DUB, _) => f64::from(decode_mulaw(buf[0])),
+        (DType::DAB, _) => f64::from(decode_alaw(buf[0])),
+        (_, Endian::Little) => LittleEndian::read_f64(buf),
+        (_, Endian::Big) => BigEndian::read_f64(buf),
+    }
+}
+
+/// guess_endian guesses the byte order of DSB/DFB/DDB/DTB/DLB data from its value statistics.
+/// It returns None for the text types, the single-byte types and for data shorter than
+/// two samples.
+///
+/// Audio signals are mostly smooth while swapped bytes look like noise, so the
+/// interpretation with the smaller sample-to-sample differences is preferred.
+/// Float data is additionally judged by how many samples decode to plausible values
+/// (finite, and zero or of a magnitude between 1e-10 and 1e10), which decides the guess
+/// if the two statistics disagree.
+pub fn guess_endian(src: &[u8], dtype: DType) -> Option<EndianGuess> {
+    let width = match dtype {
+        DType::DSB | DType::DFB | DType::DDB | DType::DTB | DType::DLB => dtype.byte_width() as usize,
+        _ => return None,
+    };
+    let n = src.len() / width;
+    if n < 2 {
+        return None;
+    }
+    let decode = |endian: Endian| -> Vec<f64> {
+        src.chunks_exact(width).map(|b| decode_sample(b, dtype, endian)).collect()
+    };
+    let little = decode(Endian::Little);
+    let big = decode(Endian::Big);
+
+    let (rl, rb) = (roughness(&little), roughness(&big));
+    let (endian, lo, hi) = if rl <= rb { (Endian::Little, rl, rb) } else { (Endian::Big, rb, rl) };
+    let smooth = EndianGuess { endian, confidence: if hi > 0. && hi.is_finite() { 1. - lo / hi } else { 0. } };
+    if dtype.is_integer() {
+        return Some(smooth);
+    }
+
+    let (pl, pb) = (plausibility(&little), plausibility(&big));
+    if pl == pb {
+        return Some(smooth);
+    }
+    let (endian, hi, lo) = if pl > pb { (Endian::Little, pl, pb) } else { (Endian::Big, pb, pl) };
+    let confidence = (hi - lo) / hi;
+    if endian == smooth.endian {
+        // Both statistics agree, so combine them as independent evidence.
+        return Some(EndianGuess { endian, confidence: 1. - (1. - confidence) * (1. - smooth.confidence) });
+    }
+    Some(EndianGuess { endian, confidence })
+}
+
+/// guess_endian_file guesses the byte order of the specified DSB/DFB/DDB file.
+/// Only the first megabyte is examined.
+pub fn guess_endian_file<P: AsRef<Path>>(filename: P) -> Result<Option<EndianGuess>> {
+    let filename = filename.as_ref();
+    let dtype = DType::from_path(filename)?;
+    let f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let mut buf: Vec<u8> = Vec::new();
+    f.take(GUESS_MAX_BYTES).read_to_end(&mut buf).map_err(|e| Error::from(e).with_path(filename))?;
+    Ok(guess_endian(&buf, dtype))
+}
+
+fn plausibility(src: &[f64]) -> f64 {
+    let ok = src
+        .iter()
+        .filter(|x| x.is_finite() && (**x == 0. || (1e-10..1e10).contains(&x.abs())))
+        .count();
+    ok as f64 / src.len() as f64
+}
+
+fn roughness(src: &[f64]) -> f64 {
+    let sum: f64 = src.windows(2).map(|w| (w[1] - w[0]).abs()).filter(|d| d.is_finite()).sum();
+    sum / (src.len() - 1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    fn sine(n: usize) -> Vec<f64> {
+        (0..n).map(|i| (2. * std::f64::consts::PI * 440. * i as f64 / 48000.).sin()).collect()
+    }
+
+    #[test]
+    fn test_big_endian_round_trip() {
+        let src: Vec<f64> = vec![1., -2., 0.5, 300.];
+        for &dtype in &[DType::DSB, DType::DFB, DType::DDB] {
+            let options = WriteOptions::new().with_scaling(Scaling::Raw).with_endian(Endian::Big);
+            let mut buf: Vec<u8> = Vec::new();
+            write_to_with(&mut buf, dtype, &src, &options).unwrap();
+
+            let (dst, _) = read_from_with(buf.as_slice(), dtype, &ReadOptions::new().with_endian(Endian::Big)).unwrap();
+            let want: Vec<f64> = match dtype {
+                DType::DSB => vec![1., -2., 1., 300.],
+                _ => src.clone(),
+            };
+            assert_eq!(dst, want);
+            assert_ne!(read_bytes(&buf, dtype).unwrap(), want);
+        }
+    }
+
+    #[test]
+    fn test_guess_endian() {
+        let src = sine(4800);
+        for &dtype in &[DType::DSB, DType::DFB, DType::DDB, DType::DTB, DType::DLB] {
+            for &endian in &[Endian::Little, Endian::Big] {
+                let options = WriteOptions::new().with_endian(endian);
+                let mut buf: Vec<u8> = Vec::new();
+                write_to_with(&mut buf, dtype, &src, &options).unwrap();
+                let guess = guess_endian(&buf, dtype).unwrap();
+                assert_eq!(guess.endian, endian, "{}", dtype);
+                assert!(guess.confidence > 0.5, "{} {:?}", dtype, guess);
+            }
+        }
+        assert!(guess_endian(b"1\n2\n", DType::DDA).is_none());
+    }
+}
@@ -0,0 +1,146 @@
+//! Error type of the dxx library.
+use std::io;
+use std::num::ParseFloatError;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::{DType, DTypeError};
+
+/// Result is the result type of the dxx library.
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Error is an error of the dxx library.
+/// Each variant describes a kind of failure, so callers can decide which ones to skip.
+#[derive(Error, Debug)]
+pub enum Error {
+    /// Io is an I/O failure. `path` is set when the failure concerns a file.
+    #[error("{}", io_message(.path, .source))]
+    Io {
+        path: Option<PathBuf>,
+        #[source]
+        source: io::Error,
+    },
+    /// Parse is a line of a text format that is not a number.
+    /// `line` and `column` start from 1; `column` points at the offending text.
+    /// `path` is set when the text was read from a file.
+    #[error("{}line {line}, column {column}: invalid number {text:?}: {source}", path_prefix(.path))]
+    Parse {
+        path: Option<PathBuf>,
+        line: usize,
+        column: usize,
+        text: String,
+        #[source]
+        source: ParseFloatError,
+    },
+    /// ColumnMismatch is a line of a text format with a different number of columns
+    /// than the first line. `path` is set when the text was read from a file.
+    #[error("{}line {line}: {got} columns, want {want}", path_prefix(.path))]
+    ColumnMismatch { path: Option<PathBuf>, line: usize, want: usize, got: usize },
+    /// SizeMismatch is binary data whose length is not a multiple of the sample width.
+    /// `path` is set when the data was read from a file.
+    #[error("{}size mismatch: {len} bytes is not a multiple of the {dtype} sample width", path_prefix(.path))]
+    SizeMismatch { path: Option<PathBuf>, len: u64, dtype: DType },
+    /// OutOfRange is a sample range that extends beyond the end of the data.
+    #[error("range {start}..{} out of bounds: only {available} samples available", .start + .len)]
+    OutOfRange { start: u64, len: u64, available: u64 },
+    /// UnsupportedType is a file suffix or type name that is not a DXX type.
+    #[error(transparent)]
+    UnsupportedType(#[from] DTypeError),
+    /// NotBinary is a text type where a binary type (DSB/DFB/DDB) is required.
+    #[error("{0} is not a binary type")]
+    NotBinary(DType),
+    /// TypeMismatch is data of type `got` accessed as type `want`.
+    #[error("type mismatch: want {want}, got {got}")]
+    TypeMismatch { want: DType, got: DType },
+    /// NonFinite is a NaN or infinite sample that is not allowed to be written.
+    #[error("non-finite sample at index {index}: {value}")]
+    NonFinite { index: usize, value: f64 },
+    /// Silent is a signal of all zeros that is not allowed to be normalized.
+    #[error("silent signal cannot be normalized")]
+    Silent,
+    /// InvalidHeader is a DXH header that cannot be decoded.
+    #[error("invalid DXH header: {0}")]
+    InvalidHeader(String),
+    /// Decode is an audio file that cannot be decoded by the `decode` feature.
+    #[error("{}: cannot decode audio: {message}", .path.display())]
+    Decode { path: PathBuf, message: String },
+    /// InvalidNpy is an NPY or NPZ file that cannot be decoded or a header that cannot be written.
+    #[error("invalid NPY file: {0}")]
+    InvalidNpy(String),
+    /// InvalidWav is a WAV file that cannot be decoded or a spec that cannot be written.
+    #[error("invalid WAV file: {0}")]
+    InvalidWav(String),
+    /// Metadata is a sidecar file that is not valid metadata.
+    #[error("{}: invalid metadata: {source}", .path.display())]
+    Metadata {
+        path: PathBuf,
+        #[source]
+        source: serde_json::Error,
+    },
+    /// ChannelMismatch is a number of samples that is not a multiple of the channel count.
+    /// `path` is set when the channel count was read from a file.
+    #[error("{}channel mismatch: {len} samples do not fit {channels} channels", path_prefix(.path))]
+    ChannelMismatch { path: Option<PathBuf>, len: usize, channels: u16 },
+    /// ChannelCount is a number of channels that differs from the expected one,
+    /// such as the columns of a text file that has metadata for another channel count.
+    #[error("channel count mismatch: want {want}, got {got}")]
+    ChannelCount { want: usize, got: usize },
+    /// ChannelLength is a channel whose length differs from the one of the first channel.
+    #[error("channel {channel} has {len} samples, want {want}")]
+    ChannelLength { channel: usize, len: usize, want: usize },
+    /// SamplingRateMismatch is a file whose sampling rate differs from the one of the files before it.
+    #[error("{}: sampling rate {got} Hz, want {want} Hz", .path.display())]
+    SamplingRateMismatch { path: PathBuf, want: u32, got: u32 },
+    /// InvalidMovement is a moving source that cannot be rendered.
+    #[error("invalid movement: {0}")]
+    InvalidMovement(String),
+    /// EmptySltf is an SLTF file without samples.
+    #[error("{}: empty SLTF", .0.display())]
+    EmptySltf(PathBuf),
+    /// UnknownLength is a WAV or NPY stream on a writer that cannot seek, whose number of
+    /// samples has not been declared.
+    #[error("a {0} stream needs its number of samples declared or a seekable writer")]
+    UnknownLength(DType),
+    /// LengthMismatch is a stream that got another number of samples than declared.
+    #[error("length mismatch: {got} samples written, {want} declared")]
+    LengthMismatch { want: u64, got: u64 },
+    /// LongSltf is an SLTF file longer than the SLTF that sizes the output of render_moving.
+    #[error("{}: SLTF of {len} samples, want at most {max}", path.display())]
+    LongSltf { path: PathBuf, len: usize, max: usize },
+}
+
+impl Error {
+    /// with_path attaches `path` to an I/O, parse, size or channel error that does not have one yet.
+    pub fn with_path<P: AsRef<Path>>(self, path: P) -> Self {
+        let path = Some(path.as_ref().to_path_buf());
+        match self {
+            Error::Io { path: None, source } => Error::Io { path, source },
+            Error::Parse { path: None, line, column, text, source } => Error::Parse { path, line, column, text, source },
+            Error::ColumnMismatch { path: None, line, want, got } => Error::ColumnMismatch { path, line, want, got },
+            Error::SizeMismatch { path: None, len, dtype } => Error::SizeMismatch { path, len, dtype },
+            Error::ChannelMismatch { path: None, len, channels } => Error::ChannelMismatch { path, len, channels },
+            e => e,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Self {
+        Error::Io { path: None, source }
+    }
+}
+
+fn path_prefix(path: &Option<PathBuf>) -> String {
+    match path {
+        Some(path) => format!("{}: ", path.display()),
+        None => String::new(),
+    }
+}
+
+fn io_message(path: &Option<PathBuf>, source: &io::Error) -> String {
+    match path {
+        Some(path) => format!("{}: {}", path.display(), source),
+        None => source.to_string(),
+    }
+}
@@ -1,22 +1,66 @@
 /// dxx is a library for io and converting audio files with a .DXX extension.
+mod channels;
+mod companding;
+mod complex;
+mod decode;
+mod dxh;
+mod endian;
+mod error;
+mod metadata;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod moving;
+mod npy;
+mod options;
+mod quantize;
+mod sample;
+mod stream;
+mod wav;
+
+use std::convert::TryFrom;
+use std::io;
 use std::io::prelude::*;
-use std::io::{BufReader, BufWriter};
+use std::io::SeekFrom;
 use std::fmt;
 use std::fmt::{Formatter, Display};
 use std::str;
 use std::str::FromStr;
 use std::fs;
 use std::fs::File;
-use byteorder::{ReadBytesExt, WriteBytesExt, LittleEndian};
-use anyhow::Result;
+use std::path::Path;
 use thiserror::Error;
 
-const TEXT_BIN_FILE_SIZE_MEAN_RATE: &'static usize = &13;
-const DSX_AMP: i16 = i16::max_value();
+use metadata::remove_metadata;
+use options::Level;
+
+pub use channels::{deinterleave, interleave, merge_channels, read_channels, read_frames, split_channels, to_frames, write_channels};
+pub use companding::{decode_alaw, decode_mulaw, encode_alaw, encode_mulaw};
+pub use complex::{read_complex_file, read_complex_file_with, write_complex_file, write_complex_file_with, ComplexLayout};
+pub use decode::read_audio;
+pub use dxh::{from_dxh, read_dxh, read_dxh_header, to_dxh, write_dxh, DxhHeader, DXH_MAGIC, DXH_VERSION};
+pub use endian::{guess_endian, guess_endian_file, Endian, EndianGuess};
+pub use error::{Error, Result};
+pub use num_complex::Complex;
+pub use metadata::{read_file_with_metadata, read_metadata, sidecar_path, write_file_with_metadata, write_metadata, Metadata};
+#[cfg(feature = "mmap")]
+pub use mmap::MappedFile;
+pub use moving::{render_moving, sltf_path, Anchor, Movement};
+pub use npy::{read_npy_header, read_npz, write_npy, write_npz, NpyHeader, NPY_MAGIC};
+pub use options::{NonFinitePolicy, Scaling, SilencePolicy, WriteOptions, WriteReport};
+pub use options::{ReadOptions, ReadReport, ReadWarning};
+pub use quantize::{Dither, Quantization, Quantizer, Rounding};
+pub use sample::{read_file_as, read_file_as_with, write_file_from, write_file_from_with, Sample};
+pub use stream::{DxxReader, DxxChunks, DxxWriter};
+pub use wav::{read_wav_spec, write_wav, WavSpec, DEFAULT_SAMPLING_RATE};
+
+const TEXT_BIN_FILE_SIZE_MEAN_RATE: &usize = &13;
+const DSX_AMP: i16 = i16::MAX;
 const DFX_AMP: f32 = 10000.;
 const DDX_AMP: f64 = 10000.;
+const DTB_AMP: f64 = 8388607.;
 
 /// DType is an enum for describing data type of file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DType {
     DSA,
     DFA,
@@ -24,6 +68,22 @@ pub enum DType {
     DSB,
     DFB,
     DDB,
+    /// DCB is 8-bit signed integer PCM.
+    DCB,
+    /// DTB is 24-bit signed integer PCM packed in three bytes.
+    DTB,
+    /// DLB is 32-bit signed integer PCM.
+    DLB,
+    /// DUB is G.711 mu-law. Its samples are read as 16-bit linear values.
+    DUB,
+    /// DAB is G.711 A-law. Its samples are read as 16-bit linear values.
+    DAB,
+    /// DXH is the self-describing container. The type of its samples is stored in its header.
+    DXH,
+    /// WAV is a RIFF/WAVE file. The type of its samples is stored in its `fmt ` chunk.
+    WAV,
+    /// NPY is a NumPy array file. The type of its samples is stored in its header.
+    NPY,
 }
 
 impl Display for DType {
@@ -35,6 +95,14 @@ impl Display for DType {
             DType::DSB => write!(f, "DSB"),
             DType::DFB => write!(f, "DFB"),
             DType::DDB => write!(f, "DDB"),
+            DType::DCB => write!(f, "DCB"),
+            DType::DTB => write!(f, "DTB"),
+            DType::DLB => write!(f, "DLB"),
+            DType::DUB => write!(f, "DUB"),
+            DType::DAB => write!(f, "DAB"),
+            DType::DXH => write!(f, "DXH"),
+            DType::WAV => write!(f, "WAV"),
+            DType::NPY => write!(f, "NPY"),
         }
     }
 }
@@ -50,6 +118,14 @@ impl FromStr for DType {
             "DSB" => Ok(DType::DSB),
             "DFB" => Ok(DType::DFB),
             "DDB" => Ok(DType::DDB),
+            "DCB" => Ok(DType::DCB),
+            "DTB" => Ok(DType::DTB),
+            "DLB" => Ok(DType::DLB),
+            "DUB" => Ok(DType::DUB),
+            "DAB" => Ok(DType::DAB),
+            "DXH" => Ok(DType::DXH),
+            "WAV" => Ok(DType::WAV),
+            "NPY" => Ok(DType::NPY),
             _ => Err(DTypeError::InvalidString(s.to_string()))
         }
     }
@@ -58,181 +134,444 @@ impl FromStr for DType {
 impl DType {
     /// from_filename determines the data type from the specified file name.
     pub fn from_filename(filename: &str) -> Result<DType, DTypeError> {
-        let suffix = match filename.split(".").last() {
-            Some(s) => s,
-            None => return Err(DTypeError::InvalidFileSuffix("".to_string()))
+        DType::from_path(filename)
+    }
+
+    /// from_path determines the data type from the extension of the specified path.
+    /// The extension is matched case-insensitively, so `noise.dsb` is read as DSB.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<DType, DTypeError> {
+        let path = path.as_ref();
+        let suffix = match path.extension() {
+            Some(s) => s.to_string_lossy(),
+            None => return Err(DTypeError::MissingFileSuffix(path.display().to_string()))
         };
-        match suffix {
+        match suffix.to_ascii_uppercase().as_str() {
             "DSA" => Ok(DType::DSA),
             "DFA" => Ok(DType::DFA),
             "DDA" => Ok(DType::DDA),
             "DSB" => Ok(DType::DSB),
             "DFB" => Ok(DType::DFB),
             "DDB" => Ok(DType::DDB),
+            "DCB" => Ok(DType::DCB),
+            "DTB" => Ok(DType::DTB),
+            "DLB" => Ok(DType::DLB),
+            "DUB" => Ok(DType::DUB),
+            "DAB" => Ok(DType::DAB),
+            "DXH" => Ok(DType::DXH),
+            "WAV" => Ok(DType::WAV),
+            "NPY" => Ok(DType::NPY),
             _ => Err(DTypeError::InvalidFileSuffix(suffix.to_string()))
         }
     }
 
+    /// is_binary reports whether the data type is a binary type, i.e. not one of DSA/DFA/DDA.
+    pub fn is_binary(&self) -> bool {
+        !matches!(*self, DType::DSA | DType::DFA | DType::DDA)
+    }
+
+    /// is_container reports whether the data type is a container (DXH, WAV or NPY)
+    /// whose sample type is stored in the file.
+    pub fn is_container(&self) -> bool {
+        matches!(*self, DType::DXH | DType::WAV | DType::NPY)
+    }
+
+    /// is_integer reports whether the samples of the data type are integers.
+    /// The companded DUB/DAB count as integers because they are read as 16-bit values.
+    pub fn is_integer(&self) -> bool {
+        match *self {
+            DType::DSA | DType::DSB | DType::DCB | DType::DTB | DType::DLB | DType::DUB | DType::DAB => true,
+            DType::DFA | DType::DDA | DType::DFB | DType::DDB | DType::DXH | DType::WAV | DType::NPY => false,
+        }
+    }
+
     /// byte_width returns the byte width of a sample.
+    /// It is 0 for DXH, WAV and NPY, whose width depends on the payload type in the file.
     pub fn byte_width(&self) -> u32 {
         match *self {
             DType::DSA | DType::DSB => 2,
             DType::DFA | DType::DFB => 4,
             DType::DDA | DType::DDB => 8,
+            DType::DCB | DType::DUB | DType::DAB => 1,
+            DType::DTB => 3,
+            DType::DLB => 4,
+            DType::DXH | DType::WAV | DType::NPY => 0,
         }
     }
 
     /// byte_width returns the bits width of a sample.
+    /// It is 0 for DXH, WAV and NPY, whose width depends on the payload type in the file.
     pub fn bits_width(&self) -> u32 {
-        match *self {
-            DType::DSA | DType::DSB => 16,
-            DType::DFA | DType::DFB => 32,
-            DType::DDA | DType::DDB => 64,
-        }
+        self.byte_width() * 8
     }
 }
 
 
 #[derive(Error, Debug)]
 pub enum DTypeError {
+    #[error("missing file suffix. want: DXX, got no extension in: {0}")]
+    MissingFileSuffix(String),
     #[error("invalid file suffix. want: DXX, got: {0}")]
     InvalidFileSuffix(String),
-    #[error("invalid string. want: [DSA, DFA, DDA, DSB, DFB, DDB], got: {0}")]
+    #[error("invalid string. want: [DSA, DFA, DDA, DSB, DFB, DDB, DCB, DTB, DLB, DUB, DAB, DXH, WAV, NPY], got: {0}")]
     InvalidString(String),
 }
 
 /// len_file returns the byte length of the specified file.
-pub fn len_file(filename: &str) -> Result<u64> {
-    let meta = fs::metadata(filename)?;
+pub fn len_file<P: AsRef<Path>>(filename: P) -> Result<u64> {
+    let filename = filename.as_ref();
+    let meta = fs::metadata(filename).map_err(|e| Error::from(e).with_path(filename))?;
     Ok(meta.len())
 }
 
+/// len_samples returns the number of samples of .DXX file without decoding them.
+/// The length of a binary file follows from its size and the header of DXH, WAV and NPY;
+/// DSA/DFA/DDA have no fixed width and are scanned.
+pub fn len_samples<P: AsRef<Path>>(filename: P) -> Result<u64> {
+    let filename = filename.as_ref();
+    let dtype = DType::from_path(filename)?;
+    let file_len = len_file(filename)?;
+    let mut f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let (dtype, len) = match dtype {
+        DType::DSA | DType::DFA | DType::DDA => {
+            let mut len = 0;
+            for x in DxxReader::new(f, dtype) {
+                x.map_err(|e| e.with_path(filename))?;
+                len += 1;
+            }
+            return Ok(len);
+        }
+        DType::DXH => {
+            let (header, offset) = DxhHeader::read(&mut io::BufReader::new(&mut f)).map_err(|e| e.with_path(filename))?;
+            (header.dtype, file_len.saturating_sub(offset))
+        }
+        DType::WAV => {
+            let (spec, offset, data_len) = WavSpec::read(&mut io::BufReader::new(&mut f)).map_err(|e| e.with_path(filename))?;
+            (spec.dtype, data_len.min(file_len.saturating_sub(offset)))
+        }
+        DType::NPY => {
+            let (header, _) = NpyHeader::read(&mut io::BufReader::new(&mut f)).map_err(|e| e.with_path(filename))?;
+            return Ok(header.len() as u64);
+        }
+        _ => (dtype, file_len),
+    };
+    let width = u64::from(dtype.byte_width());
+    if !len.is_multiple_of(width) {
+        return Err(Error::SizeMismatch { path: None, len, dtype }.with_path(filename));
+    }
+    Ok(len / width)
+}
+
 /// read_file reads .DXX file.
 /// This func determines the data type from the filename extension and reads that data.
+/// A .wav file is read as well; its samples keep the scale of the WAV sample type.
 /// The return type is Vec<f64> to make the data easier to handle.
-pub fn read_file(filename: &str) -> Result<Vec<f64>> {
-    let mut f = match File::open(filename) {
-        Ok(file) => file,
-        Err(error) => return Err(anyhow::Error::msg(format!("opening {}: {}", filename, error)))
-    };
-    let file_size = f.metadata()?.len() as usize;
-    let dtype = DType::from_filename(filename)?;
+pub fn read_file<P: AsRef<Path>>(filename: P) -> Result<Vec<f64>> {
+    let (ret, _) = read_file_with(filename, &ReadOptions::default())?;
+    Ok(ret)
+}
 
-    match dtype {
+/// read_file_with reads .DXX file with the specified options.
+/// The report holds the problems that the options allowed to be tolerated.
+pub fn read_file_with<P: AsRef<Path>>(filename: P, options: &ReadOptions) -> Result<(Vec<f64>, ReadReport)> {
+    let filename = filename.as_ref();
+    let reader = DxxReader::open(filename)?;
+    let file_size = len_file(filename)? as usize;
+    let capacity = match reader.dtype() {
         DType::DSA |
         DType::DFA |
-        DType::DDA => read_dxa(&mut f, file_size),
+        DType::DDA => file_size / *TEXT_BIN_FILE_SIZE_MEAN_RATE,
+
+        DType::DSB |
+        DType::DFB |
+        DType::DDB |
+        DType::DCB |
+        DType::DTB |
+        DType::DLB |
+        DType::DUB |
+        DType::DAB => file_size / reader.dtype().byte_width() as usize,
+
+        // open has already read the header and reports the payload type.
+        DType::DXH |
+        DType::WAV |
+        DType::NPY => unreachable!("container stream without a header"),
+    };
+    collect_samples(reader.with_options(options.clone()), capacity).map_err(|e| e.with_path(filename))
+}
 
-        DType::DSB => read_dsb(&mut f, file_size),
-        DType::DFB => read_dfb(&mut f, file_size),
-        DType::DDB => read_ddb(&mut f, file_size),
-    }
+/// read_range reads `len` samples starting at sample `start` of .DXX file.
+/// DSB/DFB/DDB are read by seeking to the byte offset, so only the range is read.
+/// DSA/DFA/DDA have no fixed width and are scanned from the beginning.
+pub fn read_range<P: AsRef<Path>>(filename: P, start: u64, len: u64) -> Result<Vec<f64>> {
+    read_range_with(filename, start, len, &ReadOptions::default())
 }
 
+/// read_range_with reads a range of samples of .DXX file with the specified options.
+pub fn read_range_with<P: AsRef<Path>>(filename: P, start: u64, len: u64, options: &ReadOptions) -> Result<Vec<f64>> {
+    let filename = filename.as_ref();
+    let dtype = DType::from_path(filename)?;
+    let mut f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+
+    // The payload of DXH starts after the header and has the byte order stored in it.
+    // The payload of WAV is the little-endian data chunk.
+    // The payload of NPY follows its header in the byte order stored in it.
+    let mut options = options.clone();
+    let file_len = len_file(filename)?;
+    let (dtype, offset, end) = match dtype {
+        DType::DXH => {
+            let (header, offset) = DxhHeader::read(&mut f).map_err(|e| e.with_path(filename))?;
+            options.endian = header.endian;
+            (header.dtype, offset, file_len)
+        }
+        DType::WAV => {
+            let (spec, offset, data_len) = WavSpec::read(&mut io::BufReader::new(&mut f)).map_err(|e| e.with_path(filename))?;
+            options.endian = Endian::Little;
+            (spec.dtype, offset, file_len.min(offset + data_len))
+        }
+        // A Fortran-order array is not stored frame by frame, so it is scanned instead.
+        DType::NPY => {
+            let (header, offset) = NpyHeader::read(&mut io::BufReader::new(&mut f)).map_err(|e| e.with_path(filename))?;
+            if header.fortran_order && header.channels() > 1 {
+                f.seek(SeekFrom::Start(0)).map_err(|e| Error::from(e).with_path(filename))?;
+                (DType::NPY, 0, file_len)
+            } else {
+                options.endian = header.endian;
+                let end = (header.len() as u64).saturating_mul(u64::from(header.dtype.byte_width())).saturating_add(offset);
+                (header.dtype, offset, file_len.min(end))
+            }
+        }
+        _ => (dtype, 0, file_len),
+    };
+
+    let skip = match dtype {
+        DType::DSA |
+        DType::DFA |
+        DType::DDA |
+        DType::NPY => start,
+
+        DType::DSB |
+        DType::DFB |
+        DType::DDB |
+        DType::DCB |
+        DType::DTB |
+        DType::DLB |
+        DType::DUB |
+        DType::DAB => {
+            let width = u64::from(dtype.byte_width());
+            let available = (end - offset) / width;
+            if !matches!(start.checked_add(len), Some(end) if end <= available) {
+                return Err(Error::OutOfRange { start, len, available });
+            }
+            f.seek(SeekFrom::Start(offset + start * width)).map_err(|e| Error::from(e).with_path(filename))?;
+            0
+        }
 
-fn read_dxa<T: Read>(src: &mut T, size: usize) -> Result<Vec<f64>> {
-    let mut ret: Vec<f64> = Vec::with_capacity(size / *TEXT_BIN_FILE_SIZE_MEAN_RATE);
-    for result in BufReader::new(src).lines() {
-        let line = result?;
-        let buf: f64 = line.parse::<f64>()?;
-        ret.push(buf);
+        DType::DXH |
+        DType::WAV => unreachable!("container payload of a container file"),
+    };
+
+    let mut reader = DxxReader::new(f, dtype).with_options(options);
+    for skipped in 0..skip {
+        match reader.next() {
+            Some(x) => x.map_err(|e| e.with_path(filename))?,
+            None => return Err(Error::OutOfRange { start, len, available: skipped }),
+        };
+    }
+    // Every sample takes at least one byte, so the payload bounds the capacity of a bogus `len`.
+    let len_usize = usize::try_from(len).unwrap_or(usize::MAX);
+    let mut ret: Vec<f64> = Vec::with_capacity(len_usize.min(usize::try_from(end - offset).unwrap_or(usize::MAX)));
+    for x in reader.take(len_usize) {
+        ret.push(x.map_err(|e| e.with_path(filename))?);
+    }
+    if (ret.len() as u64) < len {
+        // Only text formats get here: the number of samples is not known in advance.
+        return Err(Error::OutOfRange { start, len, available: start + ret.len() as u64 });
     }
     Ok(ret)
 }
 
-fn read_dsb<T: Read>(src: &mut T, size: usize) -> Result<Vec<f64>> {
-    let byte_width = DType::DSB.byte_width();
-    let mut buf: Vec<i16> = vec![0; size / byte_width as usize];
-    let mut reader = BufReader::new(src);
-    reader.read_i16_into::<LittleEndian>(&mut buf)?;
-    Ok(buf.iter().map(|x| f64::from(*x)).collect())
+/// read_from reads DXX data of the specified type from any reader.
+pub fn read_from<R: Read>(src: R, dtype: DType) -> Result<Vec<f64>> {
+    let (ret, _) = read_from_with(src, dtype, &ReadOptions::default())?;
+    Ok(ret)
 }
 
-fn read_dfb<T: Read>(src: &mut T, size: usize) -> Result<Vec<f64>> {
-    let byte_width = DType::DFB.byte_width();
-    let mut buf: Vec<f32> = vec![0.; size / byte_width as usize];
-    let mut reader = BufReader::new(src);
-    reader.read_f32_into::<LittleEndian>(&mut buf)?;
-    Ok(buf.iter().map(|x| f64::from(*x)).collect())
+/// read_from_with reads DXX data of the specified type from any reader with the specified options.
+pub fn read_from_with<R: Read>(src: R, dtype: DType, options: &ReadOptions) -> Result<(Vec<f64>, ReadReport)> {
+    collect_samples(DxxReader::new(src, dtype).with_options(options.clone()), 0)
 }
 
-fn read_ddb<T: Read>(src: &mut T, size: usize) -> Result<Vec<f64>> {
-    let byte_width = DType::DDB.byte_width();
-    let mut buf: Vec<f64> = vec![0.; size / byte_width as usize];
-    let mut reader = BufReader::new(src);
-    reader.read_f64_into::<LittleEndian>(&mut buf)?;
-    Ok(buf.iter().map(|x| f64::from(*x)).collect())
+/// read_bytes reads DXX data of the specified type from a byte slice.
+pub fn read_bytes(src: &[u8], dtype: DType) -> Result<Vec<f64>> {
+    let capacity = match dtype {
+        DType::DSA |
+        DType::DFA |
+        DType::DDA => src.len() / *TEXT_BIN_FILE_SIZE_MEAN_RATE,
+
+        DType::DSB |
+        DType::DFB |
+        DType::DDB |
+        DType::DCB |
+        DType::DTB |
+        DType::DLB |
+        DType::DUB |
+        DType::DAB => src.len() / dtype.byte_width() as usize,
+
+        DType::DXH |
+        DType::WAV |
+        DType::NPY => 0,
+    };
+    let (ret, _) = collect_samples(DxxReader::new(src, dtype), capacity)?;
+    Ok(ret)
 }
 
-/// write_file writes data to .DXX file.
-/// This func determines the data type from the filename extension and writes the data to the file.
-pub fn write_file(filename: &str, src: Vec<f64>) -> Result<()> {
-    let mut f = File::create(filename)?;
-    let dtype = DType::from_filename(filename)?;
-
-    match dtype {
-        DType::DSA => write_dxa(&mut f, f64s_to_i16s(&src, DSX_AMP)),
-        DType::DFA => write_dxa(&mut f, f64s_to_f32s(&src, DFX_AMP)),
-        DType::DDA => write_dxa(&mut f, normalize_f64s(src, DDX_AMP)),
-
-        DType::DSB => write_dsb(&mut f, f64s_to_i16s(&src, DSX_AMP)),
-        DType::DFB => write_dfb(&mut f, f64s_to_f32s(&src, DFX_AMP)),
-        DType::DDB => write_ddb(&mut f, normalize_f64s(src, DDX_AMP)),
+fn collect_samples<R: Read>(mut reader: DxxReader<R>, capacity: usize) -> Result<(Vec<f64>, ReadReport)> {
+    let mut ret: Vec<f64> = Vec::with_capacity(capacity);
+    for x in reader.by_ref() {
+        ret.push(x?);
     }
+    Ok((ret, ReadReport { warnings: reader.into_warnings() }))
 }
 
-fn write_dxa<T: Write, U: std::fmt::Display>(dst: T, src: Vec<U>) -> Result<(), > {
-    let mut writer = BufWriter::new(dst);
-    for x in src {
-        writeln!(writer, "{}", x)?;
-    }
+/// write_file writes data to .DXX file.
+/// This func determines the data type from the filename extension and writes the data to the file.
+/// A .wav file gets 16-bit PCM at 48 kHz; use write_wav for other formats.
+pub fn write_file<P: AsRef<Path>>(filename: P, src: Vec<f64>) -> Result<()> {
+    write_file_with(filename, &src, &WriteOptions::default())?;
     Ok(())
 }
 
-fn write_dsb<T: Write>(dst: T, src: Vec<i16>) -> Result<()> {
-    let mut writer = BufWriter::new(dst);
-    for x in src {
-        writer.write_i16::<LittleEndian>(x)?;
-    }
-    Ok(())
+/// write_file_with writes data to .DXX file with the specified options.
+/// Use `Scaling::Raw` to write the samples without normalization.
+/// The sidecar file of an earlier write is removed, as the data is written without metadata;
+/// use write_file_with_metadata to keep one.
+pub fn write_file_with<P: AsRef<Path>>(filename: P, src: &[f64], options: &WriteOptions) -> Result<WriteReport> {
+    let filename = filename.as_ref();
+    let dtype = DType::from_path(filename)?;
+    // The samples are checked before the file is created, so a rejected write keeps an existing file.
+    let (src, replaced, level) = check_samples(src, options)?;
+    let f = File::create(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let report = encode_to(f, dtype, &src, replaced, &level, options).map_err(|e| e.with_path(filename))?;
+    remove_metadata(filename)?;
+    Ok(report)
 }
 
-fn write_dfb<T: Write>(dst: T, src: Vec<f32>) -> Result<()> {
-    let mut writer = BufWriter::new(dst);
-    for x in src {
-        writer.write_f32::<LittleEndian>(x)?;
-    }
+/// write_files writes several signals to .DXX files with one shared normalization.
+/// Unlike calling write_file for each signal, the level differences between the signals
+/// (e.g. the interaural level difference of an L/R pair) are kept.
+pub fn write_files<P: AsRef<Path>, S: AsRef<[f64]>>(files: &[(P, S)]) -> Result<()> {
+    write_files_with(files, &WriteOptions::default())?;
     Ok(())
 }
 
-fn write_ddb<T: Write>(dst: T, src: Vec<f64>) -> Result<()> {
-    let mut writer = BufWriter::new(dst);
-    for x in src {
-        writer.write_f64::<LittleEndian>(x)?;
+/// write_files_with writes several signals to .DXX files with the specified options.
+/// The scaling is computed once over all signals and applied to each of them.
+/// A report is returned for each file in the order of `files`.
+/// As with write_file_with, the sidecar files of earlier writes are removed.
+/// The type, the channel layout and the samples of every signal are checked before the
+/// first file is created, but the writes are not atomic: if creating or writing a file
+/// fails, the files before it have already been rewritten and the ones after it are untouched.
+pub fn write_files_with<P: AsRef<Path>, S: AsRef<[f64]>>(files: &[(P, S)], options: &WriteOptions) -> Result<Vec<WriteReport>> {
+    let dtypes = files
+        .iter()
+        .map(|(filename, _)| DType::from_path(filename))
+        .collect::<Result<Vec<DType>, DTypeError>>()?;
+
+    let mut srcs: Vec<Vec<f64>> = Vec::with_capacity(files.len());
+    let mut reports: Vec<WriteReport> = Vec::with_capacity(files.len());
+    for (_, src) in files {
+        options.check_channels(src.as_ref().len())?;
+        let (src, replaced) = options.check_finite(src.as_ref())?;
+        reports.push(WriteReport { replaced, silent: Level::measure(&[&src]).is_silent(), ..WriteReport::default() });
+        srcs.push(src);
+    }
+    let level = Level::measure(&srcs);
+    options.check_silence(&level)?;
+
+    for (i, (filename, _)) in files.iter().enumerate() {
+        let filename = filename.as_ref();
+        let samples = options.scaling.apply_level(&srcs[i], dtypes[i], &level);
+        let f = File::create(filename).map_err(|e| Error::from(e).with_path(filename))?;
+        let mut writer = DxxWriter::new(f, dtypes[i])
+            .with_len(samples.len() as u64)
+            .with_quantization(options.quantization)
+            .with_endian(options.endian)
+            .with_channels(options.channels);
+        writer.write_samples(&samples).map_err(|e| e.with_path(filename))?;
+        reports[i].clipped = writer.clipped();
+        writer.finish().map_err(|e| e.with_path(filename))?;
+        remove_metadata(filename)?;
     }
+    Ok(reports)
+}
+
+/// write_to writes data of the specified type to any writer.
+/// The data is normalized in the same way as write_file.
+pub fn write_to<W: Write>(dst: W, dtype: DType, src: &[f64]) -> Result<()> {
+    write_to_with(dst, dtype, src, &WriteOptions::default())?;
     Ok(())
 }
 
-fn normalize_f64s(src: Vec<f64>, amp: f64) -> Vec<f64> {
-    let abs_src: Vec<f64> = src.iter().map(|x| x.clone().abs()).collect();
-    let max = max_f64s(&abs_src);
-    src.iter().map(|x| (x / max * amp)).collect()
+/// write_to_with writes data of the specified type to any writer with the specified options.
+pub fn write_to_with<W: Write>(dst: W, dtype: DType, src: &[f64], options: &WriteOptions) -> Result<WriteReport> {
+    let (src, replaced, level) = check_samples(src, options)?;
+    encode_to(dst, dtype, &src, replaced, &level, options)
+}
+
+/// check_samples applies the non-finite and silence policies of the options to the samples.
+/// It returns the samples to write, the indices of the replaced ones and their level.
+fn check_samples(src: &[f64], options: &WriteOptions) -> Result<(Vec<f64>, Vec<usize>, Level)> {
+    options.check_channels(src.len())?;
+    let (src, replaced) = options.check_finite(src)?;
+    let level = Level::measure(&[&src]);
+    options.check_silence(&level)?;
+    Ok((src, replaced, level))
+}
+
+/// encode_to scales and writes the samples that passed check_samples.
+fn encode_to<W: Write>(dst: W, dtype: DType, src: &[f64], replaced: Vec<usize>, level: &Level, options: &WriteOptions) -> Result<WriteReport> {
+    let samples = options.scaling.apply_level(src, dtype, level);
+    let mut writer = DxxWriter::new(dst, dtype)
+        .with_len(samples.len() as u64)
+        .with_quantization(options.quantization)
+        .with_endian(options.endian)
+        .with_channels(options.channels);
+    writer.write_samples(&samples)?;
+    let report = WriteReport { clipped: writer.clipped(), replaced, silent: level.is_silent() };
+    writer.finish()?;
+    Ok(report)
+}
+
+/// write_bytes encodes data of the specified type into a byte vector.
+/// The data is normalized in the same way as write_file.
+pub fn write_bytes(dtype: DType, src: &[f64]) -> Result<Vec<u8>> {
+    let mut buf: Vec<u8> = Vec::new();
+    write_to(&mut buf, dtype, src)?;
+    Ok(buf)
 }
 
-fn f64s_to_i16s(src: &Vec<f64>, amp: i16) -> Vec<i16> {
-    let abs_src: Vec<f64> = src.iter().map(|x| x.clone().abs()).collect();
-    let max = max_f64s(&abs_src);
-    src.iter().map(|x| (x / max * amp as f64) as i16).collect()
+/// convert converts a file to another type, e.g. a .DSB file to a .wav file.
+/// The samples are normalized in the same way as write_file, and the metadata
+/// (sampling rate, channels, ...) is carried over to the sidecar file or the header of `dst`.
+pub fn convert<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q) -> Result<()> {
+    convert_with(src, dst, &WriteOptions::default())?;
+    Ok(())
 }
 
-fn f64s_to_f32s(src: &Vec<f64>, amp: f32) -> Vec<f32> {
-    let abs_src: Vec<f64> = src.iter().map(|x| x.clone().abs()).collect();
-    let max = max_f64s(&abs_src);
-    src.iter().map(|x| (x / max * amp as f64) as f32).collect()
+/// convert_with converts a file to another type with the specified options.
+/// Use `Scaling::Raw` to keep the sample values.
+pub fn convert_with<P: AsRef<Path>, Q: AsRef<Path>>(src: P, dst: Q, options: &WriteOptions) -> Result<WriteReport> {
+    let (data, metadata) = read_file_with_metadata(src)?;
+    write_file_with_metadata(dst, &data, &metadata, options)
 }
 
-fn max_f64s(src: &Vec<f64>) -> f64 {
-    src.iter().fold(0.0 / 0.0, |m, v| v.max(m))
+fn max_f64s(src: &[f64]) -> f64 {
+    src.iter().fold(0., |m, v| v.max(m))
+}
+
+/// test_dir returns a new directory for the files of one test. It is removed when dropped.
+#[cfg(test)]
+fn test_dir() -> tempfile::TempDir {
+    tempfile::Builder::new().prefix("dxx_test_").tempdir().unwrap()
 }
 
 #[cfg(test)]
@@ -242,38 +581,232 @@ mod tests {
     #[test]
     fn test_f64s_to_i16s() {
         let src: Vec<f64> = vec![5., -2., 4., -3.];
-        assert_eq!(f64s_to_i16s(&src, DSX_AMP), vec![32767, -13106, 26213, -19660]);
+        let mut quantizer = Quantizer::new(Quantization::default());
+        let dst = quantizer.quantize_all(&Scaling::Auto.apply(&src, DType::DSB));
+        assert_eq!(dst, vec![32767, -13107, 26214, -19660]);
     }
 
     #[test]
     fn test_write_file() {
+        let tmp = test_dir();
+        let dir = tmp.path();
         let src: Vec<f64> = vec![5., -2., 4., -3.];
-        write_file("a.DSA", src).unwrap();
+        write_file(dir.join("a.DSA"), src).unwrap();
         let src: Vec<f64> = vec![5., -2., 4., -3.];
-        write_file("a.DFA", src).unwrap();
+        write_file(dir.join("a.DFA"), src).unwrap();
         let src: Vec<f64> = vec![5., -2., 4., -3.];
-        write_file("a.DDA", src).unwrap();
+        write_file(dir.join("a.DDA"), src).unwrap();
         let src: Vec<f64> = vec![5., -2., 4., -3.];
-        write_file("a.DSB", src).unwrap();
+        write_file(dir.join("a.DSB"), src).unwrap();
         let src: Vec<f64> = vec![5., -2., 4., -3.];
-        write_file("a.DFB", src).unwrap();
+        write_file(dir.join("a.DFB"), src).unwrap();
         let src: Vec<f64> = vec![5., -2., 4., -3.];
-        write_file("a.DDB", src).unwrap();
+        write_file(dir.join("a.DDB"), src).unwrap();
+    }
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(DType::from_path("noise.DSB").unwrap(), DType::DSB);
+        assert_eq!(DType::from_path("noise.dsb").unwrap(), DType::DSB);
+        assert_eq!(DType::from_path("results.v2/noise.Ddb").unwrap(), DType::DDB);
+        assert_eq!(DType::from_filename("results.v2/noise.dfa").unwrap(), DType::DFA);
+        match DType::from_path("results.v2/noise") {
+            Err(DTypeError::MissingFileSuffix(s)) => assert_eq!(s, "results.v2/noise"),
+            _ => panic!("want MissingFileSuffix"),
+        }
+        assert_eq!(DType::from_path("noise.wav").unwrap(), DType::WAV);
+        assert_eq!(DType::from_path("noise.npy").unwrap(), DType::NPY);
+        match DType::from_path("noise.mp3") {
+            Err(DTypeError::InvalidFileSuffix(s)) => assert_eq!(s, "mp3"),
+            _ => panic!("want InvalidFileSuffix"),
+        }
+    }
+
+    #[test]
+    fn test_write_files() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let l: Vec<f64> = vec![2., -1., 0.5];
+        let r: Vec<f64> = vec![1., -0.5, 0.25];
+        let files = vec![(dir.join("a_L.DDB"), l), (dir.join("a_R.DSB"), r)];
+        write_files(&files).unwrap();
+
+        assert_eq!(read_file(dir.join("a_L.DDB")).unwrap(), vec![10000., -5000., 2500.]);
+        assert_eq!(read_file(dir.join("a_R.DSB")).unwrap(), vec![16384., -8192., 4096.]);
+
+        // A signal that does not fit the channel count is rejected before any file is written.
+        let options = WriteOptions::new().with_channels(2);
+        let files = vec![(dir.join("a_L.DDB"), vec![1., 2.]), (dir.join("a_R.DSB"), vec![1., 2., 3.])];
+        let e = write_files_with(&files, &options).unwrap_err();
+        assert!(matches!(e, Error::ChannelMismatch { len: 3, channels: 2, .. }));
+        assert_eq!(read_file(dir.join("a_L.DDB")).unwrap(), vec![10000., -5000., 2500.]);
+    }
+
+    #[test]
+    fn test_rejected_write_keeps_file() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let filename = dir.join("a.DDA");
+        fs::write(&filename, "1\n2\n").unwrap();
+
+        assert!(matches!(write_file_with(&filename, &[1., f64::NAN], &WriteOptions::new()), Err(Error::NonFinite { index: 1, .. })));
+        let options = WriteOptions::new().with_silence(SilencePolicy::Error);
+        assert!(matches!(write_file_with(&filename, &[0., 0.], &options), Err(Error::Silent)));
+        assert_eq!(fs::read_to_string(&filename).unwrap(), "1\n2\n");
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        match read_file("no/such/file.DSB") {
+            Err(Error::Io { path, .. }) => assert_eq!(path.unwrap(), Path::new("no/such/file.DSB")),
+            r => panic!("want Error::Io, got {:?}", r),
+        }
+        match read_file("no/such/file.txt") {
+            Err(Error::UnsupportedType(DTypeError::InvalidFileSuffix(s))) => assert_eq!(s, "txt"),
+            r => panic!("want Error::UnsupportedType, got {:?}", r),
+        }
+        match read_bytes(b"1\n2\nx\n", DType::DDA) {
+            Err(Error::Parse { path: None, line, text, .. }) => assert_eq!((line, text.as_str()), (3, "x")),
+            r => panic!("want Error::Parse, got {:?}", r),
+        }
+
+        // Errors of a file carry its path.
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let filename = dir.join("a.DDA");
+        fs::write(&filename, "1\n2\nx\n").unwrap();
+        let err = read_file(&filename).unwrap_err();
+        assert!(matches!(&err, Error::Parse { path: Some(p), line: 3, .. } if p == &filename));
+        assert!(err.to_string().starts_with(&format!("{}: line 3", filename.display())));
+
+        let filename = dir.join("b.DDA");
+        fs::write(&filename, "1 2\n3\n").unwrap();
+        let err = read_file_with_metadata(&filename).unwrap_err();
+        assert!(matches!(&err, Error::ColumnMismatch { path: Some(p), line: 2, .. } if p == &filename));
+
+        let filename = dir.join("c.DDB");
+        fs::write(&filename, [0u8; 12]).unwrap();
+        let err = read_file(&filename).unwrap_err();
+        assert!(matches!(&err, Error::SizeMismatch { path: Some(p), len: 12, .. } if p == &filename));
+        assert!(err.to_string().starts_with(&format!("{}: size mismatch", filename.display())));
+    }
+
+    #[test]
+    fn test_read_range() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = (0..100).map(f64::from).collect();
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+        for name in &["a.DSB", "a.DFB", "a.DDB", "a.DSA", "a.DFA", "a.DDA"] {
+            let filename = dir.join(name);
+            write_file_with(&filename, &src, &options).unwrap();
+            assert_eq!(read_range(&filename, 10, 5).unwrap(), vec![10., 11., 12., 13., 14.]);
+            assert_eq!(read_range(&filename, 95, 5).unwrap(), vec![95., 96., 97., 98., 99.]);
+            assert!(read_range(&filename, 100, 0).unwrap().is_empty());
+            assert!(matches!(read_range(&filename, 200, 1), Err(Error::OutOfRange { available: 100, .. })));
+            assert!(matches!(read_range(&filename, 1, u64::MAX), Err(Error::OutOfRange { .. })));
+            assert!(matches!(read_range(&filename, u64::MAX, 1), Err(Error::OutOfRange { .. })));
+            match read_range(&filename, 98, 5) {
+                Err(Error::OutOfRange { available, .. }) => assert_eq!(available, 100),
+                r => panic!("want Error::OutOfRange, got {:?}", r),
+            }
+        }
+    }
+
+    #[test]
+    fn test_len_samples() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = (0..10).map(f64::from).collect();
+        let stereo = Metadata::new().with_channels(2);
+        for name in &["a.DSA", "a.DDA", "a.DSB", "a.DTB", "a.DUB", "a.DXH", "a.wav", "a.npy"] {
+            let filename = dir.join(name);
+            write_file_with_metadata(&filename, &src, &stereo, &WriteOptions::new()).unwrap();
+            assert_eq!(len_samples(&filename).unwrap(), 10, "{}", name);
+        }
+
+        fs::write(dir.join("b.DDB"), [0u8; 12]).unwrap();
+        assert!(matches!(len_samples(dir.join("b.DDB")), Err(Error::SizeMismatch { len: 12, .. })));
+    }
+
+    #[test]
+    fn test_read_file_size_mismatch() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let filename = dir.join("a.DDB");
+        // A DFB with an odd number of samples renamed to DDB.
+        fs::write(&filename, write_bytes(DType::DFB, &[1., 2., 3.]).unwrap()).unwrap();
+
+        let err = read_file(&filename).unwrap_err();
+        assert!(matches!(err, Error::SizeMismatch { len: 12, .. }));
+
+        let (data, report) = read_file_with(&filename, &ReadOptions::new().with_lenient(true)).unwrap();
+        assert_eq!(data.len(), 1);
+        assert_eq!(report.warnings, vec![ReadWarning::TrailingBytes { len: 12, trailing: 4, width: 8 }]);
+    }
+
+    #[test]
+    fn test_read_write_bytes() {
+        let src: Vec<f64> = vec![5., -2., 4., -3.];
+        let buf = write_bytes(DType::DSB, &src).unwrap();
+        assert_eq!(buf.len(), 8);
+        assert_eq!(read_bytes(&buf, DType::DSB).unwrap(), vec![32767., -13107., 26214., -19660.]);
+
+        let buf = write_bytes(DType::DDA, &src).unwrap();
+        assert_eq!(String::from_utf8(buf.clone()).unwrap(), "10000\n-4000\n8000\n-6000\n");
+        assert_eq!(read_from(buf.as_slice(), DType::DDA).unwrap(), vec![10000., -4000., 8000., -6000.]);
+    }
+
+    #[test]
+    fn test_integer_types() {
+        for &(dtype, width, full_scale) in &[(DType::DCB, 1, 127f64), (DType::DTB, 3, 8388607.), (DType::DLB, 4, 2147483647.)] {
+            assert_eq!(dtype.to_string().parse::<DType>().unwrap(), dtype);
+            assert_eq!(dtype.byte_width(), width);
+            assert_eq!(dtype.bits_width(), width * 8);
+
+            let buf = write_bytes(dtype, &[1., -0.5, 0.25]).unwrap();
+            assert_eq!(buf.len(), 3 * width as usize);
+            let want = vec![full_scale, (-full_scale / 2.).round(), (full_scale / 4.).round()];
+            assert_eq!(read_bytes(&buf, dtype).unwrap(), want);
+
+            let (data, _) = read_from_with(buf.as_slice(), dtype, &ReadOptions::new().with_normalize_integers(true)).unwrap();
+            assert!((data[0] - 1.).abs() < 0.01 && (data[1] + 0.5).abs() < 0.01, "{} {:?}", dtype, data);
+        }
+
+        // mu-law and A-law hold 16-bit samples at a reduced resolution.
+        let src: Vec<f64> = vec![32767., -16384., 1000., 0.];
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+        for &dtype in &[DType::DUB, DType::DAB] {
+            let mut buf: Vec<u8> = Vec::new();
+            write_to_with(&mut buf, dtype, &src, &options).unwrap();
+            assert_eq!(buf.len(), 4);
+            let dst = read_bytes(&buf, dtype).unwrap();
+            for (x, y) in src.iter().zip(dst.iter()) {
+                assert!((x - y).abs() <= x.abs() / 32. + 8., "{} {} {}", dtype, x, y);
+            }
+        }
     }
 
     #[test]
     fn test_convert() {
-        let data = read_file("sine.DSB").unwrap();
-        write_file("sine.DSA", data).unwrap();
-        let data = read_file("sine.DSB").unwrap();
-        write_file("sine.DFA", data).unwrap();
-        let data = read_file("sine.DSB").unwrap();
-        write_file("sine.DFB", data).unwrap();
-        let data = read_file("sine.DSB").unwrap();
-        write_file("sine.DDA", data).unwrap();
-        let data = read_file("sine.DSB").unwrap();
-        write_file("sine.DDB", data).unwrap();
-        let data = read_file("sine.DSA").unwrap();
-        write_file("sine1.DSB", data).unwrap();
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let sine: Vec<f64> = (0..4800)
+            .map(|n| (2. * std::f64::consts::PI * 440. * n as f64 / 48000.).sin())
+            .collect();
+        write_file(dir.join("sine.DSB"), sine).unwrap();
+
+        let data = read_file(dir.join("sine.DSB")).unwrap();
+        write_file(dir.join("sine.DSA"), data).unwrap();
+        let data = read_file(dir.join("sine.DSB")).unwrap();
+        write_file(dir.join("sine.DFA"), data).unwrap();
+        let data = read_file(dir.join("sine.DSB")).unwrap();
+        write_file(dir.join("sine.DFB"), data).unwrap();
+        let data = read_file(dir.join("sine.DSB")).unwrap();
+        write_file(dir.join("sine.DDA"), data).unwrap();
+        let data = read_file(dir.join("sine.DSB")).unwrap();
+        write_file(dir.join("sine.DDB"), data).unwrap();
+        let data = read_file(dir.join("sine.DSA")).unwrap();
+        write_file(dir.join("sine1.DSB"), data).unwrap();
     }
 }
@@ -0,0 +1,366 @@
+//! Sidecar metadata of .DXX files.
+//!
+//! DXX files have no header, so everything that is not a sample is kept in a JSON file
+//! next to the data: the metadata of `name.DSB` is stored in `name.DSB.json`.
+//! DXH files carry the same metadata in their header instead, and WAV files carry
+//! the sampling rate and the channel count in their `fmt ` chunk. The shape of an NPY
+//! array tells its channel count, and the rest is kept in a sidecar file.
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Result};
+use crate::dxh::{read_dxh_header, write_dxh, DxhHeader};
+use crate::options::{WriteOptions, WriteReport};
+use crate::npy::read_npy_header;
+use crate::wav::{read_wav_spec, write_wav, WavSpec};
+use crate::{write_file_with, DType, DxxReader};
+
+/// SIDECAR_SUFFIX is appended to the data file name to get the sidecar file name.
+const SIDECAR_SUFFIX: &str = ".json";
+
+/// Metadata describes the data of a .DXX file.
+/// Every field is optional in the sidecar file; `channels` defaults to 1.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    /// sampling_rate is the sampling frequency in Hz.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sampling_rate: Option<u32>,
+    /// channels is the number of interleaved channels.
+    pub channels: u16,
+    /// units is the physical unit of a sample, such as "Pa".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub units: Option<String>,
+    /// calibration_gain converts a sample to `units` by multiplication.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub calibration_gain: Option<f64>,
+    /// creator is the tool that wrote the data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub creator: Option<String>,
+    /// tags are free-form key/value pairs.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Metadata {
+            sampling_rate: None,
+            channels: 1,
+            units: None,
+            calibration_gain: None,
+            creator: None,
+            tags: BTreeMap::new(),
+        }
+    }
+}
+
+impl Metadata {
+    /// new returns metadata of a single channel with nothing else set.
+    pub fn new() -> Self {
+        Metadata::default()
+    }
+
+    /// with_sampling_rate returns the metadata with the specified sampling rate.
+    pub fn with_sampling_rate(mut self, sampling_rate: u32) -> Self {
+        self.sampling_rate = Some(sampling_rate);
+        self
+    }
+
+    /// with_channels returns the metadata with the specified number of channels.
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// with_units returns the metadata with the specified units.
+    pub fn with_units(mut self, units: &str) -> Self {
+        self.units = Some(units.to_string());
+        self
+    }
+
+    /// with_calibration_gain returns the metadata with the specified calibration gain.
+    pub fn with_calibration_gain(mut self, gain: f64) -> Self {
+        self.calibration_gain = Some(gain);
+        self
+    }
+
+    /// with_creator returns the metadata with the specified creator tool.
+    pub fn with_creator(mut self, creator: &str) -> Self {
+        self.creator = Some(creator.to_string());
+        self
+    }
+
+    /// with_tag returns the metadata with the specified tag added.
+    pub fn with_tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// validate checks that `len` samples fit the channel layout.
+    pub fn validate(&self, len: usize) -> Result<()> {
+        if self.channels == 0 || !len.is_multiple_of(usize::from(self.channels)) {
+            return Err(Error::ChannelMismatch { path: None, len, channels: self.channels });
+        }
+        Ok(())
+    }
+
+    /// frames returns the number of samples per channel of `len` interleaved samples.
+    pub fn frames(&self, len: usize) -> usize {
+        len / usize::from(self.channels.max(1))
+    }
+
+    /// duration returns the length in seconds of `len` interleaved samples,
+    /// or None if the sampling rate is unknown.
+    pub fn duration(&self, len: usize) -> Option<f64> {
+        self.sampling_rate.map(|rate| self.frames(len) as f64 / f64::from(rate))
+    }
+}
+
+/// sidecar_path returns the path of the sidecar file of the specified data file.
+pub fn sidecar_path<P: AsRef<Path>>(filename: P) -> PathBuf {
+    let mut name: OsString = filename.as_ref().as_os_str().to_owned();
+    name.push(SIDECAR_SUFFIX);
+    PathBuf::from(name)
+}
+
+/// read_metadata reads the sidecar file of the specified data file.
+/// It returns None if there is no sidecar file.
+/// The metadata of a DXH file is read from its header, and the one of a WAV file
+/// from its `fmt ` chunk. An NPY file without a sidecar file gets the channels of its shape.
+pub fn read_metadata<P: AsRef<Path>>(filename: P) -> Result<Option<Metadata>> {
+    let filename = filename.as_ref();
+    match DType::from_path(filename) {
+        Ok(DType::DXH) => return Ok(Some(read_dxh_header(filename)?.metadata)),
+        Ok(DType::WAV) => return Ok(Some(read_wav_spec(filename)?.metadata())),
+        _ => {}
+    }
+    let npy_channels = match DType::from_path(filename) {
+        Ok(DType::NPY) => Some(read_npy_header(filename)?.channels()),
+        _ => None,
+    };
+    let path = sidecar_path(filename);
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        // The shape of an NPY array still tells its channels.
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return match npy_channels {
+                Some(channels) => {
+                    let channels = u16::try_from(channels).map_err(|_| Error::InvalidNpy(format!("too many columns: {}", channels)))?;
+                    Ok(Some(Metadata::new().with_channels(channels)))
+                }
+                None => Ok(None),
+            };
+        }
+        Err(e) => return Err(Error::from(e).with_path(&path)),
+    };
+    let metadata: Metadata = serde_json::from_str(&text).map_err(|source| Error::Metadata { path: path.clone(), source })?;
+    if metadata.channels == 0 {
+        return Err(Error::ChannelMismatch { path: None, len: 0, channels: 0 }.with_path(&path));
+    }
+    Ok(Some(metadata))
+}
+
+/// write_metadata writes the sidecar file of the specified data file.
+/// It always writes a sidecar file; the header of a DXH file is written by write_dxh.
+pub fn write_metadata<P: AsRef<Path>>(filename: P, metadata: &Metadata) -> Result<()> {
+    let path = sidecar_path(filename);
+    let mut text = serde_json::to_string_pretty(metadata).map_err(|source| Error::Metadata { path: path.clone(), source })?;
+    text.push('\n');
+    fs::write(&path, text).map_err(|e| Error::from(e).with_path(&path))
+}
+
+/// remove_metadata removes the sidecar file of the specified data file, if there is one.
+/// The writers without metadata call it, so that a rewritten file does not keep the
+/// sampling rate and the channel count of the file it replaced.
+pub(crate) fn remove_metadata<P: AsRef<Path>>(filename: P) -> Result<()> {
+    let path = sidecar_path(filename);
+    match fs::remove_file(&path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::from(e).with_path(&path)),
+        _ => Ok(()),
+    }
+}
+
+/// read_file_with_metadata reads .DXX file and its metadata.
+/// Without metadata, a text file has as many channels as columns and a binary file has one.
+/// Text with several columns must have one column per channel.
+pub fn read_file_with_metadata<P: AsRef<Path>>(filename: P) -> Result<(Vec<f64>, Metadata)> {
+    let filename = filename.as_ref();
+    let sidecar = read_metadata(filename)?;
+    let mut reader = DxxReader::open(filename)?;
+    let mut data: Vec<f64> = Vec::new();
+    for x in reader.by_ref() {
+        data.push(x.map_err(|e| e.with_path(filename))?);
+    }
+
+    let columns = reader.columns().unwrap_or(1);
+    let metadata = match sidecar {
+        Some(metadata) => metadata,
+        None => {
+            let channels = u16::try_from(columns).map_err(|_| Error::ChannelCount { want: usize::from(u16::MAX), got: columns })?;
+            Metadata::new().with_channels(channels)
+        }
+    };
+    if columns > 1 && columns != usize::from(metadata.channels) {
+        return Err(Error::ChannelCount { want: usize::from(metadata.channels), got: columns });
+    }
+    metadata.validate(data.len())?;
+    Ok((data, metadata))
+}
+
+/// write_file_with_metadata writes .DXX file and its sidecar file.
+/// The samples are interleaved as `metadata.channels` channels, so the text formats
+/// get one column per channel. A DXH file gets the metadata in its header instead.
+/// A WAV file gets 16-bit PCM samples with the sampling rate (48 kHz if unknown) and
+/// the channel count of the metadata; the rest of the metadata is dropped.
+/// Nothing is written if the data does not fit the channel layout.
+/// The calibration gain is divided by the gain of the scaling, so that the written
+/// samples still convert to `units`.
+pub fn write_file_with_metadata<P: AsRef<Path>>(filename: P, src: &[f64], metadata: &Metadata, options: &WriteOptions) -> Result<WriteReport> {
+    let filename = filename.as_ref();
+    metadata.validate(src.len())?;
+    let options = options.clone().with_channels(metadata.channels);
+    let dtype = DType::from_path(filename)?;
+    let metadata = &calibrate(metadata, src, dtype, &options)?;
+    match dtype {
+        DType::DXH => {
+            let header = DxhHeader::new(DType::DDB).with_endian(options.endian).with_metadata(metadata.clone());
+            return write_dxh(filename, src, &header, &options);
+        }
+        DType::WAV => return write_wav(filename, src, &WavSpec::from_metadata(DType::DSB, metadata), &options),
+        _ => {}
+    }
+    let report = write_file_with(filename, src, &options)?;
+    write_metadata(filename, metadata)?;
+    Ok(report)
+}
+
+/// calibrate returns the metadata of `src` scaled with `options`.
+/// A silent signal keeps its calibration gain, since it has no gain to undo.
+fn calibrate(metadata: &Metadata, src: &[f64], dtype: DType, options: &WriteOptions) -> Result<Metadata> {
+    let mut metadata = metadata.clone();
+    if let Some(calibration_gain) = metadata.calibration_gain {
+        let level = options.check_level(src)?;
+        let gain = options.scaling.gain_level(dtype, &level);
+        if gain != 0. {
+            metadata.calibration_gain = Some(calibration_gain / gain);
+        }
+    }
+    Ok(metadata)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::*;
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let filename = dir.join("a.DDB");
+        let metadata = Metadata::new()
+            .with_sampling_rate(48000)
+            .with_channels(2)
+            .with_units("Pa")
+            .with_calibration_gain(0.5)
+            .with_creator("dxx")
+            .with_tag("room", "anechoic");
+        let src: Vec<f64> = vec![1., -1., 0.5, -0.5];
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+
+        write_file_with_metadata(&filename, &src, &metadata, &options).unwrap();
+        assert_eq!(sidecar_path(&filename), dir.join("a.DDB.json"));
+        let (data, got) = read_file_with_metadata(&filename).unwrap();
+        assert_eq!(data, src);
+        assert_eq!(got, metadata);
+        assert_eq!(got.frames(data.len()), 2);
+        assert_eq!(got.duration(data.len()), Some(2. / 48000.));
+
+        // Missing fields take their defaults.
+        fs::write(sidecar_path(&filename), r#"{"sampling_rate": 44100}"#).unwrap();
+        let got = read_metadata(&filename).unwrap().unwrap();
+        assert_eq!(got, Metadata::new().with_sampling_rate(44100));
+
+        fs::remove_file(sidecar_path(&filename)).unwrap();
+        assert_eq!(read_metadata(&filename).unwrap(), None);
+        assert_eq!(read_file_with_metadata(&filename).unwrap().1, Metadata::new());
+    }
+
+    #[test]
+    fn test_calibration_gain() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let metadata = Metadata::new().with_units("Pa").with_calibration_gain(0.5);
+        let src: Vec<f64> = vec![1., -2., 0.5];
+
+        // Peak(1.) halves the samples, so the calibration gain doubles and the pascals are kept.
+        for name in &["a.DDB", "a.DXH"] {
+            let filename = dir.join(name);
+            write_file_with_metadata(&filename, &src, &metadata, &WriteOptions::new().with_scaling(Scaling::Peak(1.))).unwrap();
+            let (data, got) = read_file_with_metadata(&filename).unwrap();
+            assert_eq!(data, vec![0.5, -1., 0.25]);
+            assert_eq!(got.calibration_gain, Some(1.));
+        }
+
+        let filename = dir.join("a.DDB");
+        write_file_with_metadata(&filename, &src, &metadata, &WriteOptions::new().with_scaling(Scaling::Gain(4.))).unwrap();
+        assert_eq!(read_metadata(&filename).unwrap().unwrap().calibration_gain, Some(0.125));
+        write_file_with_metadata(&filename, &src, &metadata, &WriteOptions::new().with_scaling(Scaling::Raw)).unwrap();
+        assert_eq!(read_metadata(&filename).unwrap().unwrap().calibration_gain, Some(0.5));
+
+        // A replaced sample does not count towards the peak.
+        let options = WriteOptions::new()
+            .with_scaling(Scaling::Peak(1.))
+            .with_non_finite(NonFinitePolicy::ReplaceWithZero);
+        write_file_with_metadata(&filename, &[1., f64::INFINITY, -2.], &metadata, &options).unwrap();
+        assert_eq!(read_metadata(&filename).unwrap().unwrap().calibration_gain, Some(1.));
+        let e = write_file_with_metadata(&filename, &[1., f64::NAN], &metadata, &WriteOptions::new()).unwrap_err();
+        assert!(matches!(e, Error::NonFinite { index: 1, .. }));
+    }
+
+    #[test]
+    fn test_stale_sidecar() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let filename = dir.join("a.DDB");
+        let stereo = Metadata::new().with_sampling_rate(44100).with_channels(2);
+        write_file_with_metadata(&filename, &[1., 2.], &stereo, &WriteOptions::new()).unwrap();
+        assert_eq!(read_metadata(&filename).unwrap(), Some(stereo));
+
+        // Rewriting the file without metadata removes the sidecar of the earlier write.
+        write_file(&filename, vec![1., 2., 3.]).unwrap();
+        assert!(!sidecar_path(&filename).exists());
+        assert_eq!(read_metadata(&filename).unwrap(), None);
+        assert_eq!(read_file_with_metadata(&filename).unwrap().1, Metadata::new().with_channels(1));
+    }
+
+    #[test]
+    fn test_metadata_errors() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let filename = dir.join("a.DDB");
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+        let stereo = Metadata::new().with_channels(2);
+
+        let e = write_file_with_metadata(&filename, &[1., 2., 3.], &stereo, &options).unwrap_err();
+        assert!(matches!(e, Error::ChannelMismatch { len: 3, channels: 2, .. }));
+
+        write_file_with(&filename, &[1., 2., 3.], &options).unwrap();
+        write_metadata(&filename, &stereo).unwrap();
+        let e = read_file_with_metadata(&filename).unwrap_err();
+        assert!(matches!(e, Error::ChannelMismatch { len: 3, channels: 2, .. }));
+
+        fs::write(sidecar_path(&filename), "{").unwrap();
+        assert!(matches!(read_metadata(&filename), Err(Error::Metadata { .. })));
+        fs::write(sidecar_path(&filename), r#"{"channels": 0}"#).unwrap();
+        let sidecar = sidecar_path(&filename);
+        assert!(matches!(read_metadata(&filename), Err(Error::ChannelMismatch { path: Some(p), channels: 0, .. }) if p == sidecar));
+    }
+}
@@ -0,0 +1,224 @@
+//! Memory-mapped access to DSB/DFB/DDB/DXH/WAV/NPY files.
+//!
+//! This module is enabled by the `mmap` feature.
+use std::borrow::Cow;
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::dxh::DxhHeader;
+use crate::endian::{decode_sample, Endian};
+use crate::error::{Error, Result};
+use crate::npy::NpyHeader;
+use crate::wav::WavSpec;
+use crate::DType;
+
+/// MappedFile is a DSB/DFB/DDB/DXH/WAV/NPY file mapped into memory.
+///
+/// The samples are borrowed straight from the mapping when the byte order of the file
+/// is the native one and the mapping is aligned for the sample type.
+/// Otherwise they are decoded into a new vector.
+pub struct MappedFile {
+    map: Mmap,
+    offset: usize,
+    end: usize,
+    dtype: DType,
+    endian: Endian,
+}
+
+impl MappedFile {
+    /// open maps the specified little-endian DSB/DFB/DDB file.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<Self> {
+        MappedFile::open_with_endian(filename, Endian::Little)
+    }
+
+    /// open_with_endian maps the specified DSB/DFB/DDB file of the specified byte order.
+    /// The type and the byte order of a DXH, WAV or NPY file are taken from its header instead.
+    /// A Fortran-order NPY array of more than one column is rejected, as its samples are not interleaved.
+    pub fn open_with_endian<P: AsRef<Path>>(filename: P, endian: Endian) -> Result<Self> {
+        let filename = filename.as_ref();
+        let dtype = DType::from_path(filename)?;
+        if !dtype.is_binary() {
+            return Err(Error::NotBinary(dtype));
+        }
+        let f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+        // SAFETY: the mapping is read-only. As with any memory map, the file must not be
+        // truncated by another process while it is mapped.
+        let map = unsafe { Mmap::map(&f) }.map_err(|e| Error::from(e).with_path(filename))?;
+
+        let (dtype, endian, offset, end) = match dtype {
+            DType::DXH => {
+                let (header, offset) = DxhHeader::read(&mut &map[..]).map_err(|e| e.with_path(filename))?;
+                (header.dtype, header.endian, offset as usize, map.len())
+            }
+            DType::WAV => {
+                let (spec, offset, len) = WavSpec::read(&mut &map[..]).map_err(|e| e.with_path(filename))?;
+                let end = map.len().min((offset + len) as usize);
+                (spec.dtype, Endian::Little, offset as usize, end)
+            }
+            DType::NPY => {
+                let (header, offset) = NpyHeader::read(&mut &map[..]).map_err(|e| e.with_path(filename))?;
+                if header.fortran_order && header.channels() > 1 {
+                    return Err(Error::InvalidNpy(format!("{}: Fortran-order array cannot be mapped", filename.display())));
+                }
+                let end = map.len().min(offset as usize + header.len() * header.dtype.byte_width() as usize);
+                (header.dtype, header.endian, offset as usize, end)
+            }
+            _ => (dtype, endian, 0, map.len()),
+        };
+
+        let len = (end - offset) as u64;
+        if !len.is_multiple_of(u64::from(dtype.byte_width())) {
+            return Err(Error::SizeMismatch { path: None, len, dtype }.with_path(filename));
+        }
+        Ok(MappedFile { map, offset, end, dtype, endian })
+    }
+
+    /// dtype returns the data type of the samples, which is the payload type for DXH.
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
+
+    /// len returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.payload().len() / self.dtype.byte_width() as usize
+    }
+
+    /// is_empty reports whether the file has no samples.
+    pub fn is_empty(&self) -> bool {
+        self.payload().is_empty()
+    }
+
+    /// as_i16s returns the samples of a DSB file.
+    pub fn as_i16s(&self) -> Result<Cow<'_, [i16]>> {
+        self.check_type(DType::DSB)?;
+        Ok(self.view().unwrap_or_else(|| self.decode().map(|x| x as i16).collect()))
+    }
+
+    /// as_f32s returns the samples of a DFB file.
+    pub fn as_f32s(&self) -> Result<Cow<'_, [f32]>> {
+        self.check_type(DType::DFB)?;
+        Ok(self.view().unwrap_or_else(|| self.decode().map(|x| x as f32).collect()))
+    }
+
+    /// as_f64s returns the samples of a DDB file.
+    pub fn as_f64s(&self) -> Result<Cow<'_, [f64]>> {
+        self.check_type(DType::DDB)?;
+        Ok(self.view().unwrap_or_else(|| self.decode().collect()))
+    }
+
+    /// to_f64s converts the samples of any binary type to f64.
+    pub fn to_f64s(&self) -> Vec<f64> {
+        self.decode().collect()
+    }
+
+    fn payload(&self) -> &[u8] {
+        &self.map[self.offset..self.end]
+    }
+
+    fn check_type(&self, want: DType) -> Result<()> {
+        if self.dtype != want {
+            return Err(Error::TypeMismatch { want, got: self.dtype });
+        }
+        Ok(())
+    }
+
+    fn decode(&self) -> impl Iterator<Item = f64> + '_ {
+        let width = self.dtype.byte_width() as usize;
+        self.payload().chunks_exact(width).map(move |b| decode_sample(b, self.dtype, self.endian))
+    }
+
+    /// view borrows the mapping as a slice of T if no conversion is needed.
+    fn view<T: Pod + Clone>(&self) -> Option<Cow<'_, [T]>> {
+        if self.endian != native_endian() {
+            return None;
+        }
+        // SAFETY: T is one of i16, f32 and f64, for which every bit pattern is valid.
+        let (head, body, tail) = unsafe { self.payload().align_to::<T>() };
+        if !head.is_empty() || !tail.is_empty() {
+            return None;
+        }
+        Some(Cow::Borrowed(body))
+    }
+}
+
+/// Pod marks the sample types that can be viewed from raw bytes.
+trait Pod {}
+
+impl Pod for i16 {}
+impl Pod for f32 {}
+impl Pod for f64 {}
+
+fn native_endian() -> Endian {
+    if cfg!(target_endian = "big") {
+        Endian::Big
+    } else {
+        Endian::Little
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::fs;
+    use std::io::Write;
+
+    use crate::*;
+
+    #[test]
+    fn test_mapped_file() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = vec![1., -2., 3., -4.];
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+
+        let filename = dir.join("a.DDB");
+        write_file_with(&filename, &src, &options).unwrap();
+        let mapped = MappedFile::open(&filename).unwrap();
+        assert_eq!(mapped.len(), 4);
+        let view = mapped.as_f64s().unwrap();
+        assert!(matches!(view, Cow::Borrowed(_)));
+        assert_eq!(&*view, src.as_slice());
+        assert!(matches!(mapped.as_i16s(), Err(Error::TypeMismatch { .. })));
+
+        let filename = dir.join("a.DSB");
+        write_file_with(&filename, &src, &options).unwrap();
+        let mapped = MappedFile::open(&filename).unwrap();
+        assert_eq!(&*mapped.as_i16s().unwrap(), &[1, -2, 3, -4]);
+        assert_eq!(mapped.to_f64s(), src);
+
+        // A foreign byte order is decoded into a copy.
+        let filename = dir.join("b.DFB");
+        write_file_with(&filename, &src, &options.clone().with_endian(Endian::Big)).unwrap();
+        let mapped = MappedFile::open_with_endian(&filename, Endian::Big).unwrap();
+        let view = mapped.as_f32s().unwrap();
+        assert!(matches!(view, Cow::Owned(_)));
+        assert_eq!(&*view, &[1., -2., 3., -4.]);
+
+        // The padded DXH header keeps the payload aligned.
+        let filename = dir.join("c.DXH");
+        write_dxh(&filename, &src, &DxhHeader::new(DType::DFB), &options).unwrap();
+        let mapped = MappedFile::open(&filename).unwrap();
+        assert_eq!(mapped.dtype(), DType::DFB);
+        let view = mapped.as_f32s().unwrap();
+        assert!(matches!(view, Cow::Borrowed(_)));
+        assert_eq!(&*view, &[1., -2., 3., -4.]);
+
+        // The samples of a WAV file end with its data chunk.
+        let filename = dir.join("d.wav");
+        write_wav(&filename, &src, &WavSpec::new(DType::DSB), &options).unwrap();
+        fs::OpenOptions::new().append(true).open(&filename).unwrap().write_all(b"LIST\0\0\0\0").unwrap();
+        let mapped = MappedFile::open(&filename).unwrap();
+        assert_eq!(mapped.len(), 4);
+        assert_eq!(&*mapped.as_i16s().unwrap(), &[1, -2, 3, -4]);
+
+        // The NPY header is padded to keep the payload aligned, too.
+        let filename = dir.join("e.npy");
+        write_npy(&filename, &src, &NpyHeader::new(DType::DDB, &[2, 2]), &options).unwrap();
+        let mapped = MappedFile::open(&filename).unwrap();
+        let view = mapped.as_f64s().unwrap();
+        assert!(matches!(view, Cow::Borrowed(_)));
+        assert_eq!(&*view, src.as_slice());
+    }
+}
@@ -0,0 +1,250 @@
+//! Moving-source rendering of the overlap-add tools.
+//!
+//! A sound is cut into one segment per angle step, each segment is convolved with the
+//! SLTF (spatial transfer function) of its angle and the results are overlap-added.
+//! `Anchor` tells which of the overlap-add tools is followed: the movement starts at,
+//! is centered on or ends at the given angle.
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::read_file;
+
+/// Anchor is where the given angle lies on the movement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    /// Start begins the movement at the angle, like overlap-add-start-360.
+    Start,
+    /// Middle centers the movement on the angle, like overlap-add-middle.
+    Middle,
+    /// End finishes the movement at the angle, like overlap-add-end-360.
+    End,
+}
+
+/// Movement describes a moving source.
+/// Angles are counted in steps, of which a full turn has `steps`:
+/// 3600 for the 0.1 degree SLTFs and 360 for the 1 degree SLTFs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movement {
+    /// move_width is the width of the movement [step].
+    pub move_width: u32,
+    /// move_velocity is the velocity of the movement [step/sec].
+    pub move_velocity: u32,
+    /// angle is the angle the movement is anchored to [step].
+    pub angle: u32,
+    /// clockwise is the direction of the movement.
+    pub clockwise: bool,
+    /// anchor is where the angle lies on the movement.
+    pub anchor: Anchor,
+    /// steps is the number of steps of a full turn.
+    pub steps: u32,
+}
+
+impl Movement {
+    /// new returns a clockwise movement centered on `angle` with 3600 steps a turn.
+    pub fn new(move_width: u32, move_velocity: u32, angle: u32) -> Self {
+        Movement { move_width, move_velocity, angle, clockwise: true, anchor: Anchor::Middle, steps: 3600 }
+    }
+
+    /// with_clockwise returns the movement in the specified direction.
+    pub fn with_clockwise(mut self, clockwise: bool) -> Self {
+        self.clockwise = clockwise;
+        self
+    }
+
+    /// with_anchor returns the movement with the angle at `anchor`.
+    pub fn with_anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    /// with_steps returns the movement with `steps` steps a full turn.
+    pub fn with_steps(mut self, steps: u32) -> Self {
+        self.steps = steps;
+        self
+    }
+
+    /// move_samples returns the duration of the movement [sample].
+    pub fn move_samples(&self, sampling_rate: u32) -> usize {
+        // 移動時間 [sec]
+        let move_time = f64::from(self.move_width) / f64::from(self.move_velocity);
+        (move_time * f64::from(sampling_rate)) as usize
+    }
+
+    /// samples_per_step returns the number of samples needed to move by one step.
+    pub fn samples_per_step(&self, sampling_rate: u32) -> usize {
+        self.move_samples(sampling_rate) / self.move_width as usize
+    }
+
+    /// sound_len returns the number of samples of the sound that the movement uses.
+    pub fn sound_len(&self, sampling_rate: u32) -> usize {
+        self.samples_per_step(sampling_rate) * self.move_width as usize
+    }
+
+    /// check reports why the movement cannot be rendered at `sampling_rate`.
+    pub fn check(&self, sampling_rate: u32) -> Result<()> {
+        let invalid = |message: String| Err(Error::InvalidMovement(message));
+        if self.move_width == 0 || self.move_velocity == 0 || self.steps == 0 {
+            return invalid("move_width, move_velocity and steps must be positive".to_string());
+        }
+        if self.move_width > self.steps {
+            return invalid(format!("move_width must be at most {}, got {}", self.steps, self.move_width));
+        }
+        if self.samples_per_step(sampling_rate) == 0 {
+            return invalid("the movement is too fast for the sampling rate".to_string());
+        }
+        Ok(())
+    }
+
+    /// angles returns the SLTF angle of every step of the movement.
+    pub fn angles(&self) -> Vec<i32> {
+        let move_width = self.move_width as i32;
+        let angle = self.angle as i32;
+        let steps = self.steps as i32;
+        let start_angle = match (self.anchor, self.clockwise) {
+            (Anchor::Start, _) => angle,
+            (Anchor::Middle, true) => angle - move_width / 2,
+            (Anchor::Middle, false) => angle + move_width / 2 - 1,
+            (Anchor::End, true) => angle - move_width + 1,
+            (Anchor::End, false) => angle + move_width - 1,
+        };
+        let start_angle = if start_angle < 0 { start_angle + steps } else { start_angle } % steps;
+
+        let mut angles = Vec::with_capacity(move_width as usize);
+        for i in 0..move_width {
+            let mut data_angle = i % (move_width * 2);
+            if data_angle > move_width {
+                data_angle = move_width * 2 - data_angle
+            }
+            if !self.clockwise {
+                data_angle = -data_angle
+            }
+            if data_angle < 0 {
+                data_angle += steps
+            }
+            angles.push((start_angle + data_angle) % steps);
+        }
+        angles
+    }
+}
+
+/// sltf_path returns the path of the SLTF of the subject at the angle for the ear `lr`.
+pub fn sltf_path<P: AsRef<Path>>(subject: P, angle: i32, lr: &str) -> PathBuf {
+    subject.as_ref().join("SLTF").join(format!("SLTF_{}_{}.DDB", angle, lr))
+}
+
+/// render_moving overlap-adds the sound convolved with the SLTFs of the subject along
+/// the movement, for the ear `lr` ("L" or "R").
+/// As in the overlap-add tools, the output has the length of the movement plus the length
+/// of the SLTF of angle 0 for the left ear minus one, and a longer SLTF on the way is an error.
+/// The SLTFs are read one step at a time.
+pub fn render_moving<P: AsRef<Path>>(subject: P, sound: &[f64], sampling_rate: u32, movement: &Movement, lr: &str) -> Result<Vec<f64>> {
+    movement.check(sampling_rate)?;
+    let sound_len = movement.sound_len(sampling_rate);
+    if sound.len() < sound_len {
+        return Err(Error::OutOfRange { start: 0, len: sound_len as u64, available: sound.len() as u64 });
+    }
+    let samples_per_step = movement.samples_per_step(sampling_rate);
+    let sltf_len = read_sltf(sltf_path(&subject, 0, "L"))?.len();
+
+    let mut move_out: Vec<f64> = vec![0.; movement.move_samples(sampling_rate) + sltf_len - 1];
+    for (i, angle) in movement.angles().into_iter().enumerate() {
+        // SLTFの読み込み
+        let path = sltf_path(&subject, angle, lr);
+        let sltf = read_sltf(&path)?;
+        if sltf.len() > sltf_len {
+            return Err(Error::LongSltf { path, len: sltf.len(), max: sltf_len });
+        }
+        // 音データと伝達関数の畳込み
+        let cut_sound = &sound[samples_per_step * i..samples_per_step * (i + 1)];
+        let sound_sltf = linear_conv(cut_sound, &sltf);
+        // Overlap-Add
+        for (j, v) in sound_sltf.iter().enumerate() {
+            move_out[samples_per_step * i + j] += v
+        }
+    }
+    Ok(move_out)
+}
+
+fn read_sltf<P: AsRef<Path>>(path: P) -> Result<Vec<f64>> {
+    let sltf = read_file(&path)?;
+    if sltf.is_empty() {
+        return Err(Error::EmptySltf(path.as_ref().to_path_buf()));
+    }
+    Ok(sltf)
+}
+
+fn linear_conv(x: &[f64], y: &[f64]) -> Vec<f64> {
+    let conv_len = x.len() + y.len() - 1;
+    let mut ret: Vec<f64> = vec![0.; conv_len];
+    for p in 0..x.len() {
+        for n in p..y.len() + p {
+            ret[n] += x[p] * y[n - p];
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::*;
+
+    fn movement(move_width: u32, angle: u32, clockwise: bool, anchor: Anchor) -> Movement {
+        Movement::new(move_width, 10, angle).with_clockwise(clockwise).with_anchor(anchor).with_steps(360)
+    }
+
+    #[test]
+    fn test_angles() {
+        assert_eq!(movement(10, 0, true, Anchor::Middle).angles(), vec![355, 356, 357, 358, 359, 0, 1, 2, 3, 4]);
+        assert_eq!(movement(10, 0, false, Anchor::Middle).angles(), vec![4, 3, 2, 1, 0, 359, 358, 357, 356, 355]);
+        assert_eq!(movement(10, 0, true, Anchor::Start).angles(), vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(movement(10, 0, false, Anchor::Start).angles(), vec![0, 359, 358, 357, 356, 355, 354, 353, 352, 351]);
+        assert_eq!(movement(10, 0, true, Anchor::End).angles(), vec![351, 352, 353, 354, 355, 356, 357, 358, 359, 0]);
+        assert_eq!(movement(10, 0, false, Anchor::End).angles(), vec![9, 8, 7, 6, 5, 4, 3, 2, 1, 0]);
+
+        let m = Movement::new(10, 10, 3595).with_clockwise(false);
+        assert_eq!(m.angles(), vec![3599, 3598, 3597, 3596, 3595, 3594, 3593, 3592, 3591, 3590]);
+    }
+
+    #[test]
+    fn test_check() {
+        assert!(Movement::new(10, 10, 0).check(48000).is_ok());
+        assert!(matches!(Movement::new(0, 10, 0).check(48000), Err(Error::InvalidMovement(_))));
+        assert!(matches!(Movement::new(10, 0, 0).check(48000), Err(Error::InvalidMovement(_))));
+        assert!(matches!(Movement::new(3601, 10, 0).check(48000), Err(Error::InvalidMovement(_))));
+        assert!(matches!(Movement::new(10, 100, 0).check(4), Err(Error::InvalidMovement(_))));
+    }
+
+    #[test]
+    fn test_render_moving() {
+        let tmp = test_dir();
+        let subject = tmp.path();
+        fs::create_dir_all(subject.join("SLTF")).unwrap();
+        let raw = WriteOptions::new().with_scaling(Scaling::Raw);
+        // The SLTF of angle a is a delay of a samples, four samples long.
+        for angle in 0..4 {
+            let mut sltf = vec![0.; 4];
+            sltf[angle as usize] = 1.;
+            write_file_with(sltf_path(subject, angle, "L"), &sltf, &raw).unwrap();
+        }
+
+        let m = Movement::new(2, 2, 0).with_anchor(Anchor::Start).with_steps(360);
+        let sound = vec![1., 2., 3., 4., 5.];
+        // Two samples per step: [1, 2] through angle 0 and [3, 4] through angle 1.
+        let got = render_moving(subject, &sound, 4, &m, "L").unwrap();
+        assert_eq!(got, vec![1., 2., 0., 3., 4., 0., 0.]);
+        let got = render_moving(subject, &sound, 4, &m.clone().with_clockwise(false).with_anchor(Anchor::End), "L").unwrap();
+        assert_eq!(got, vec![0., 1., 5., 4., 0., 0., 0.]);
+
+        assert!(matches!(render_moving(subject, &sound[..3], 4, &m, "L"), Err(Error::OutOfRange { len: 4, available: 3, .. })));
+        assert!(matches!(render_moving(subject, &sound, 4, &m, "R"), Err(Error::Io { .. })));
+        write_file_with(sltf_path(subject, 1, "R"), &[1.], &raw).unwrap();
+        fs::write(sltf_path(subject, 0, "R"), b"").unwrap();
+        assert!(matches!(render_moving(subject, &sound, 4, &m, "R"), Err(Error::EmptySltf(_))));
+
+        // The output is sized from the SLTF of angle 0 for the left ear.
+        write_file_with(sltf_path(subject, 0, "R"), &[1.; 5], &raw).unwrap();
+        assert!(matches!(render_moving(subject, &sound, 4, &m, "R"), Err(Error::LongSltf { len: 5, max: 4, .. })));
+    }
+}
@@ -0,0 +1,462 @@
+//! NumPy .npy arrays and .npz bundles.
+//!
+//! An NPY file is read and written like a DXH file: its header names the sample type
+//! of the payload, which maps onto the binary DXX types of the same width:
+//!
+//! | NumPy dtype | payload |
+//! |-------------|---------|
+//! | int8 | DCB |
+//! | int16 | DSB |
+//! | int32 | DLB |
+//! | float32 | DFB |
+//! | float64 | DDB |
+//!
+//! A 1-D array is a single channel and a 2-D array of shape (frames, channels) holds
+//! interleaved channels, which is the C order that NumPy uses by default.
+//! Fortran-order arrays are read as well. An NPZ file is a zip archive of NPY files.
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use zip::result::ZipError;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::dxh::Counter;
+use crate::endian::Endian;
+use crate::error::{Error, Result};
+use crate::options::{Level, Scaling, WriteOptions, WriteReport};
+use crate::stream::DxxWriter;
+use crate::{read_bytes, write_to_with, DType};
+
+/// NPY_MAGIC is the first six bytes of an NPY file.
+pub const NPY_MAGIC: [u8; 6] = *b"\x93NUMPY";
+/// NPY_ALIGN is the alignment of the payload.
+const NPY_ALIGN: usize = 64;
+/// NPY_SUFFIX is the suffix of the NPY files in an NPZ archive.
+const NPY_SUFFIX: &str = ".npy";
+
+/// NpyHeader is the header of an NPY file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NpyHeader {
+    /// dtype is the type of the payload: DCB, DSB, DLB, DFB or DDB.
+    pub dtype: DType,
+    /// endian is the byte order of the payload.
+    pub endian: Endian,
+    /// fortran_order reports whether a 2-D array is stored channel by channel.
+    pub fortran_order: bool,
+    /// shape is the shape of the array: (samples,) or (frames, channels).
+    pub shape: Vec<usize>,
+}
+
+impl NpyHeader {
+    /// new returns the header of a little-endian C-order array of the specified shape.
+    pub fn new(dtype: DType, shape: &[usize]) -> Self {
+        NpyHeader { dtype, endian: Endian::default(), fortran_order: false, shape: shape.to_vec() }
+    }
+
+    /// with_endian returns the header with the specified byte order.
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// len returns the number of samples of the array.
+    pub fn len(&self) -> usize {
+        self.shape.iter().product()
+    }
+
+    /// is_empty reports whether the array has no samples.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// channels returns the number of channels: the second dimension of a 2-D array, or 1.
+    pub fn channels(&self) -> usize {
+        match self.shape.len() {
+            2 => self.shape[1],
+            _ => 1,
+        }
+    }
+
+    /// read reads a header from `src` and returns it with its length in bytes,
+    /// padding included. `src` is left at the first byte of the payload.
+    pub fn read<R: Read>(src: &mut R) -> Result<(Self, u64)> {
+        let mut src = Counter { inner: src, count: 0 };
+        let mut magic = [0u8; 8];
+        src.read_exact(&mut magic).map_err(truncated)?;
+        if magic[..6] != NPY_MAGIC {
+            return Err(invalid("bad magic number"));
+        }
+        let len = match magic[6] {
+            1 => usize::from(src.read_u16::<LittleEndian>().map_err(truncated)?),
+            2 | 3 => src.read_u32::<LittleEndian>().map_err(truncated)? as usize,
+            major => return Err(invalid(&format!("unsupported version {}.{}", major, magic[7]))),
+        };
+        // The buffer grows with the bytes actually read, so a bogus length cannot allocate much.
+        let mut buf: Vec<u8> = Vec::new();
+        (&mut src).take(len as u64).read_to_end(&mut buf)?;
+        if buf.len() != len {
+            return Err(invalid("truncated"));
+        }
+        let text = String::from_utf8(buf).map_err(|_| invalid("header is not UTF-8"))?;
+        let header = parse_header(&text)?;
+        Ok((header, src.count))
+    }
+
+    /// write writes the header to `dst` and returns its length in bytes, padding included.
+    pub fn write<W: Write>(&self, dst: &mut W) -> Result<u64> {
+        self.write_padded(dst, 0)
+    }
+
+    /// write_padded writes the header padded to at least `min_len` bytes, so that a header
+    /// written over a longer placeholder keeps the payload where it is.
+    pub(crate) fn write_padded<W: Write>(&self, dst: &mut W, min_len: u64) -> Result<u64> {
+        let descr = self.descr()?;
+        let shape = match self.shape.len() {
+            1 => format!("({},)", self.shape[0]),
+            _ => format!("({})", self.shape.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(", ")),
+        };
+        let order = if self.fortran_order { "True" } else { "False" };
+        let mut text = format!("{{'descr': '{}', 'fortran_order': {}, 'shape': {}, }}", descr, order, shape);
+        // The header ends with a newline, and the payload starts at a multiple of 64 bytes.
+        // Version 1.0 stores the padded length in 16 bits; longer headers need version 2.0.
+        let padded = |prefix: usize| {
+            let len = (prefix + text.len() + 1).max(min_len as usize);
+            len + (NPY_ALIGN - len % NPY_ALIGN) % NPY_ALIGN - prefix
+        };
+        let (version, header_len) = match u16::try_from(padded(10)) {
+            Ok(len) => (1, usize::from(len)),
+            Err(_) => (2, padded(12)),
+        };
+        text.push_str(&" ".repeat(header_len - text.len() - 1));
+        text.push('\n');
+
+        let mut buf: Vec<u8> = Vec::new();
+        buf.extend_from_slice(&NPY_MAGIC);
+        buf.extend_from_slice(&[version, 0]);
+        match version {
+            1 => buf.write_u16::<LittleEndian>(u16::try_from(text.len()).map_err(|_| invalid("header too long"))?)?,
+            _ => buf.write_u32::<LittleEndian>(u32::try_from(text.len()).map_err(|_| invalid("header too long"))?)?,
+        }
+        buf.extend_from_slice(text.as_bytes());
+        dst.write_all(&buf)?;
+        Ok(buf.len() as u64)
+    }
+
+    /// descr returns the NumPy type string of the payload, e.g. `<f8`.
+    fn descr(&self) -> Result<String> {
+        let kind = match self.dtype {
+            DType::DCB => return Ok("|i1".to_string()),
+            DType::DSB => "i2",
+            DType::DLB => "i4",
+            DType::DFB => "f4",
+            DType::DDB => "f8",
+            dtype => return Err(invalid(&format!("{} cannot be stored in an NPY file", dtype))),
+        };
+        let order = match self.endian {
+            Endian::Little => '<',
+            Endian::Big => '>',
+        };
+        Ok(format!("{}{}", order, kind))
+    }
+}
+
+/// read_npy_header reads the header of the specified NPY file.
+pub fn read_npy_header<P: AsRef<Path>>(filename: P) -> Result<NpyHeader> {
+    let filename = filename.as_ref();
+    let f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let (header, _) = NpyHeader::read(&mut BufReader::new(f)).map_err(|e| e.with_path(filename))?;
+    Ok(header)
+}
+
+/// write_npy writes samples to the specified NPY file with the specified header.
+/// The shape of the header has to hold the samples; a 2-D shape makes interleaved channels.
+/// The samples are scaled for the payload type of the header, and the byte order
+/// of the header takes precedence over the one of the options.
+pub fn write_npy<P: AsRef<Path>>(filename: P, src: &[f64], header: &NpyHeader, options: &WriteOptions) -> Result<WriteReport> {
+    let filename = filename.as_ref();
+    if header.len() != src.len() || header.shape.len() > 2 {
+        return Err(invalid(&format!("shape {:?} does not fit {} samples", header.shape, src.len())));
+    }
+    header.descr()?;
+    let (src, replaced) = options.check_finite(src)?;
+    let level = Level::measure(&[&src]);
+    options.check_silence(&level)?;
+
+    let samples = options.scaling.apply(&src, header.dtype);
+    let f = File::create(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let mut writer = DxxWriter::new(f, DType::NPY)
+        .with_npy_header(header.clone())
+        .with_len(samples.len() as u64)
+        .with_quantization(options.quantization);
+    writer.write_samples(&samples).map_err(|e| e.with_path(filename))?;
+    let report = WriteReport { clipped: writer.clipped(), replaced, silent: level.is_silent() };
+    writer.finish().map_err(|e| e.with_path(filename))?;
+    Ok(report)
+}
+
+/// read_npz reads every array of the specified NPZ file in the order of the archive.
+/// Each array comes with its name, without the `.npy` suffix, and its header.
+/// The samples of a 2-D array are interleaved as in read_file.
+/// Both `numpy.savez` and `numpy.savez_compressed` archives can be read.
+pub fn read_npz<P: AsRef<Path>>(filename: P) -> Result<Vec<(String, Vec<f64>, NpyHeader)>> {
+    let filename = filename.as_ref();
+    let f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let archive_len = f.metadata().map_err(|e| Error::from(e).with_path(filename))?.len();
+    let mut archive = ZipArchive::new(BufReader::new(f)).map_err(|e| zip_error(e).with_path(filename))?;
+    let mut ret: Vec<(String, Vec<f64>, NpyHeader)> = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i).map_err(|e| zip_error(e).with_path(filename))?;
+        if entry.is_dir() {
+            continue;
+        }
+        let name = entry.name().to_string();
+        // The size in the archive is only a hint; a member cannot be larger than the archive
+        // unless it is compressed, and then the buffer grows as it is inflated.
+        let mut buf: Vec<u8> = Vec::with_capacity(entry.size().min(archive_len) as usize);
+        entry.read_to_end(&mut buf).map_err(|e| Error::from(e).with_path(filename))?;
+        let (header, _) = NpyHeader::read(&mut buf.as_slice()).map_err(|e| in_member(e, &name))?;
+        let data = read_bytes(&buf, DType::NPY).map_err(|e| in_member(e, &name))?;
+        let name = name.strip_suffix(NPY_SUFFIX).unwrap_or(&name).to_string();
+        ret.push((name, data, header));
+    }
+    Ok(ret)
+}
+
+/// write_npz writes named signals to the specified NPZ file as 1-D float64 arrays,
+/// like `numpy.savez`. The samples are written as they are, without normalization.
+/// `numpy.load` returns the arrays under the same names.
+pub fn write_npz<P: AsRef<Path>, S: AsRef<str>, D: AsRef<[f64]>>(filename: P, arrays: &[(S, D)]) -> Result<()> {
+    let filename = filename.as_ref();
+    let f = File::create(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let mut archive = ZipWriter::new(BufWriter::new(f));
+    let file_options = FileOptions::default().compression_method(CompressionMethod::Stored);
+    let options = WriteOptions::new().with_scaling(Scaling::Raw);
+    for (name, src) in arrays {
+        let name = format!("{}{}", name.as_ref(), NPY_SUFFIX);
+        archive.start_file(name, file_options).map_err(|e| zip_error(e).with_path(filename))?;
+        write_to_with(&mut archive, DType::NPY, src.as_ref(), &options).map_err(|e| e.with_path(filename))?;
+    }
+    let mut f = archive.finish().map_err(|e| zip_error(e).with_path(filename))?;
+    f.flush().map_err(|e| Error::from(e).with_path(filename))?;
+    Ok(())
+}
+
+/// parse_header parses the Python dict literal of an NPY header, such as
+/// `{'descr': '<f8', 'fortran_order': False, 'shape': (3, 2), }`.
+fn parse_header(text: &str) -> Result<NpyHeader> {
+    let descr = value_of(text, "descr")?;
+    let descr = descr.trim_matches(|c| c == '\'' || c == '"');
+    let (endian, kind) = match descr.chars().next() {
+        Some('<') | Some('|') => (Endian::Little, &descr[1..]),
+        Some('>') => (Endian::Big, &descr[1..]),
+        Some('=') => (native_endian(), &descr[1..]),
+        _ => (native_endian(), descr),
+    };
+    let dtype = match kind {
+        "i1" | "b" => DType::DCB,
+        "i2" | "h" => DType::DSB,
+        "i4" | "i" => DType::DLB,
+        "f4" | "f" => DType::DFB,
+        "f8" | "d" => DType::DDB,
+        _ => return Err(invalid(&format!("unsupported dtype {:?}", descr))),
+    };
+    let fortran_order = match value_of(text, "fortran_order")? {
+        "True" => true,
+        "False" => false,
+        s => return Err(invalid(&format!("invalid fortran_order {:?}", s))),
+    };
+    let shape = value_of(text, "shape")?;
+    let shape = shape
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.trim_end_matches('L').parse::<usize>().map_err(|_| invalid(&format!("invalid shape {:?}", shape))))
+        .collect::<Result<Vec<usize>>>()?;
+    if shape.len() > 2 {
+        return Err(invalid(&format!("{}-D arrays are not supported", shape.len())));
+    }
+    let size = shape.iter().try_fold(dtype.byte_width() as usize, |size, &n| size.checked_mul(n));
+    if size.is_none() {
+        return Err(invalid(&format!("shape {:?} is too large", shape)));
+    }
+    Ok(NpyHeader { dtype, endian, fortran_order, shape })
+}
+
+/// value_of returns the text of the value of `key` in a Python dict literal.
+fn value_of<'a>(text: &'a str, key: &str) -> Result<&'a str> {
+    let start = [format!("'{}'", key), format!("\"{}\"", key)]
+        .iter()
+        .find_map(|k| text.find(k.as_str()).map(|i| i + k.len()))
+        .ok_or_else(|| invalid(&format!("missing {}", key)))?;
+    let rest = text[start..].trim_start().strip_prefix(':').ok_or_else(|| invalid(&format!("invalid {}", key)))?.trim_start();
+    // A tuple may contain commas, other values end at the next comma or brace.
+    let end = if rest.starts_with('(') { rest.find(')').map(|i| i + 1) } else { rest.find([',', '}']) };
+    Ok(rest[..end.ok_or_else(|| invalid(&format!("invalid {}", key)))?].trim())
+}
+
+fn native_endian() -> Endian {
+    if cfg!(target_endian = "big") {
+        Endian::Big
+    } else {
+        Endian::Little
+    }
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidNpy(reason.to_string())
+}
+
+fn in_member(e: Error, name: &str) -> Error {
+    match e {
+        Error::InvalidNpy(reason) => Error::InvalidNpy(format!("{}: {}", name, reason)),
+        e => e,
+    }
+}
+
+fn truncated(e: io::Error) -> Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        return invalid("truncated");
+    }
+    e.into()
+}
+
+fn zip_error(e: ZipError) -> Error {
+    match e {
+        ZipError::Io(e) => e.into(),
+        e => invalid(&format!("npz: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::*;
+
+    #[test]
+    fn test_npy_header() {
+        let header = NpyHeader::new(DType::DDB, &[4]);
+        let mut buf: Vec<u8> = Vec::new();
+        let len = header.write(&mut buf).unwrap();
+        assert_eq!(len as usize, buf.len());
+        assert_eq!(len % 64, 0);
+        assert_eq!(&buf[..10], b"\x93NUMPY\x01\x00\x76\x00");
+        let text = String::from_utf8(buf[10..].to_vec()).unwrap();
+        assert_eq!(text.trim_end(), "{'descr': '<f8', 'fortran_order': False, 'shape': (4,), }");
+        assert!(text.ends_with('\n'));
+        assert_eq!(NpyHeader::read(&mut buf.as_slice()).unwrap(), (header, len));
+
+        // A big-endian Fortran-order array is stored channel by channel.
+        let header = NpyHeader { dtype: DType::DSB, endian: Endian::Big, fortran_order: true, shape: vec![3, 2] };
+        let mut buf: Vec<u8> = Vec::new();
+        header.write(&mut buf).unwrap();
+        for x in &[1i16, 2, 3, -1, -2, -3] {
+            buf.extend_from_slice(&x.to_be_bytes());
+        }
+        assert_eq!(NpyHeader::read(&mut buf.as_slice()).unwrap().0, header);
+        assert_eq!(read_bytes(&buf, DType::NPY).unwrap(), vec![1., -1., 2., -2., 3., -3.]);
+
+        let text = b"\x93NUMPY\x01\x00\x2a\x00{'descr': '<c16', 'fortran_order': False, 'shape': (), }";
+        assert!(matches!(NpyHeader::read(&mut &text[..]), Err(Error::InvalidNpy(_))));
+        assert!(matches!(NpyHeader::read(&mut &b"\x93NUMPY\x01"[..]), Err(Error::InvalidNpy(_))));
+        // A header length beyond the end of the data is truncated, not allocated.
+        assert!(matches!(NpyHeader::read(&mut &b"\x93NUMPY\x02\x00\xff\xff\xff\xff{"[..]), Err(Error::InvalidNpy(_))));
+        let text = format!("{{'descr': '<f8', 'fortran_order': True, 'shape': ({}, 2), }}", usize::MAX);
+        let mut buf = b"\x93NUMPY\x01\x00".to_vec();
+        buf.extend_from_slice(&(text.len() as u16).to_le_bytes());
+        buf.extend_from_slice(text.as_bytes());
+        assert!(matches!(NpyHeader::read(&mut buf.as_slice()), Err(Error::InvalidNpy(_))));
+        // A shape larger than the payload is truncated, not allocated.
+        let text = "{'descr': '<f8', 'fortran_order': True, 'shape': (1000000000, 2), }";
+        let mut buf = b"\x93NUMPY\x01\x00".to_vec();
+        buf.extend_from_slice(&(text.len() as u16).to_le_bytes());
+        buf.extend_from_slice(text.as_bytes());
+        buf.extend_from_slice(&[0u8; 32]);
+        assert!(matches!(read_bytes(&buf, DType::NPY), Err(Error::InvalidNpy(_))));
+        assert!(NpyHeader::new(DType::DTB, &[1]).write(&mut Vec::new()).is_err());
+
+        // Headers around the 64 KiB limit of version 1.0 switch to version 2.0 after padding.
+        for n in 21_820..21_850 {
+            let header = NpyHeader::new(DType::DDB, &vec![1; n]);
+            let mut buf: Vec<u8> = Vec::new();
+            let len = header.write(&mut buf).unwrap() as usize;
+            assert_eq!((len, len % 64), (buf.len(), 0));
+            let text_len = match buf[6] {
+                1 => 10 + usize::from(u16::from_le_bytes([buf[8], buf[9]])),
+                _ => 12 + u32::from_le_bytes([buf[8], buf[9], buf[10], buf[11]]) as usize,
+            };
+            assert_eq!(text_len, len, "{} dimensions", n);
+            assert_eq!(buf[len - 1], b'\n');
+        }
+    }
+
+    #[test]
+    fn test_npy_file() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = vec![1., -2., 3., -4., 5., -6.];
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+
+        for &dtype in &[DType::DCB, DType::DSB, DType::DLB, DType::DFB, DType::DDB] {
+            for &channels in &[1, 2] {
+                let filename = dir.join(format!("{}_{}.npy", dtype, channels));
+                let shape: Vec<usize> = if channels == 1 { vec![6] } else { vec![3, 2] };
+                write_npy(&filename, &src, &NpyHeader::new(dtype, &shape), &options).unwrap();
+                assert_eq!(read_npy_header(&filename).unwrap().shape, shape);
+                assert_eq!(read_file(&filename).unwrap(), src, "{} {}", dtype, channels);
+                assert_eq!(read_range(&filename, 1, 3).unwrap(), &src[1..4]);
+                let (_, metadata) = read_file_with_metadata(&filename).unwrap();
+                assert_eq!(metadata.channels, channels);
+            }
+        }
+
+        // write_file stores float64 with the usual DDB normalization.
+        assert_eq!(DType::from_path("a.npy").unwrap(), DType::NPY);
+        write_file(dir.join("a.npy"), src.clone()).unwrap();
+        assert_eq!(read_npy_header(dir.join("a.npy")).unwrap(), NpyHeader::new(DType::DDB, &[6]));
+        let metadata = Metadata::new().with_sampling_rate(48000).with_channels(3);
+        write_file_with_metadata(dir.join("b.npy"), &src, &metadata, &options).unwrap();
+        assert_eq!(read_npy_header(dir.join("b.npy")).unwrap().shape, vec![2, 3]);
+        assert_eq!(read_file_with_metadata(dir.join("b.npy")).unwrap(), (src.clone(), metadata));
+
+        let e = write_npy(dir.join("c.npy"), &src, &NpyHeader::new(DType::DDB, &[4]), &options).unwrap_err();
+        assert!(matches!(e, Error::InvalidNpy(_)));
+    }
+
+    #[test]
+    fn test_npz_file() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let l: Vec<f64> = vec![0.5, -0.25, 0.125];
+        let r: Vec<f64> = vec![1e-9, 2., -3.];
+        write_npz(dir.join("sltf.npz"), &[("SLTF_0_L", &l), ("SLTF_0_R", &r)]).unwrap();
+
+        let arrays = read_npz(dir.join("sltf.npz")).unwrap();
+        assert_eq!(arrays.len(), 2);
+        assert_eq!((arrays[0].0.as_str(), &arrays[0].1), ("SLTF_0_L", &l));
+        assert_eq!((arrays[1].0.as_str(), &arrays[1].1), ("SLTF_0_R", &r));
+        assert_eq!(arrays[1].2, NpyHeader::new(DType::DDB, &[3]));
+
+        // numpy.savez_compressed deflates the members.
+        let f = fs::File::create(dir.join("compressed.npz")).unwrap();
+        let mut archive = zip::ZipWriter::new(f);
+        let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        archive.start_file("x.npy", options).unwrap();
+        std::io::Write::write_all(&mut archive, &write_bytes(DType::NPY, &l).unwrap()).unwrap();
+        archive.finish().unwrap();
+        let arrays = read_npz(dir.join("compressed.npz")).unwrap();
+        assert_eq!(arrays[0].0, "x");
+        assert_eq!(arrays[0].1, vec![10000., -5000., 2500.]);
+
+        fs::write(dir.join("broken.npz"), b"PK").unwrap();
+        assert!(matches!(read_npz(dir.join("broken.npz")), Err(Error::InvalidNpy(_))));
+    }
+}
@@ -0,0 +1,469 @@
+//! Options for writing .DXX files.
+use std::fmt;
+
+use crate::endian::Endian;
+use crate::quantize::Quantization;
+use crate::error::Error;
+use crate::metadata::Metadata;
+use crate::{DType, DDX_AMP, DFX_AMP, DSX_AMP, DTB_AMP};
+use crate::max_f64s;
+
+/// Scaling describes how samples are scaled before they are written.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Scaling {
+    /// Auto peak-normalizes to the standard amplitude of the data type,
+    /// i.e. the largest value of the integer types (32767 for DSA/DSB and the companded
+    /// DUB/DAB) and 10000 for the floating-point types.
+    /// This is what write_file does.
+    #[default]
+    Auto,
+    /// Raw writes the samples as they are.
+    Raw,
+    /// Peak normalizes the samples so that the absolute peak equals the given amplitude.
+    Peak(f64),
+    /// Gain multiplies the samples by a fixed factor.
+    Gain(f64),
+    /// Rms normalizes the samples so that their RMS equals the given value.
+    Rms(f64),
+}
+
+impl Scaling {
+    /// apply returns the scaled samples that are written as data of `dtype`.
+    /// A silent signal is returned as zeros by the normalizing scalings.
+    /// DXH and NPY are scaled like their default payload DDB, and WAV like its default payload DSB.
+    pub fn apply(&self, src: &[f64], dtype: DType) -> Vec<f64> {
+        self.apply_level(src, dtype, &Level::measure(&[src]))
+    }
+
+    /// apply_jointly scales each of `srcs` with one gain shared by all of them,
+    /// so that the level relationships between the signals are kept.
+    /// The peak and the RMS are measured over all signals together.
+    pub fn apply_jointly<S: AsRef<[f64]>>(&self, srcs: &[S], dtype: DType) -> Vec<Vec<f64>> {
+        let level = Level::measure(srcs);
+        srcs.iter().map(|src| self.apply_level(src.as_ref(), dtype, &level)).collect()
+    }
+
+    /// normalizes reports whether the gain of this scaling depends on the level of the signal.
+    pub fn normalizes(&self) -> bool {
+        match *self {
+            Scaling::Auto | Scaling::Peak(_) | Scaling::Rms(_) => true,
+            Scaling::Raw | Scaling::Gain(_) => false,
+        }
+    }
+
+    pub(crate) fn apply_level(&self, src: &[f64], dtype: DType, level: &Level) -> Vec<f64> {
+        match *self {
+            Scaling::Auto => scale_f64s(src, level.peak, auto_amp(dtype)),
+            Scaling::Raw => src.to_vec(),
+            Scaling::Peak(amp) => scale_f64s(src, level.peak, amp),
+            Scaling::Gain(gain) => src.iter().map(|x| x * gain).collect(),
+            Scaling::Rms(target) => scale_f64s(src, level.rms, target),
+        }
+    }
+
+    /// gain_level returns the factor that apply_level multiplies the samples by, 0 for a silent signal.
+    pub(crate) fn gain_level(&self, dtype: DType, level: &Level) -> f64 {
+        let ratio = |amp: f64, reference: f64| if reference == 0. { 0. } else { amp / reference };
+        match *self {
+            Scaling::Auto => ratio(auto_amp(dtype), level.peak),
+            Scaling::Raw => 1.,
+            Scaling::Peak(amp) => ratio(amp, level.peak),
+            Scaling::Gain(gain) => gain,
+            Scaling::Rms(target) => ratio(target, level.rms),
+        }
+    }
+}
+
+/// auto_amp returns the peak that `Scaling::Auto` normalizes data of `dtype` to.
+fn auto_amp(dtype: DType) -> f64 {
+    match dtype {
+        DType::DSA | DType::DSB => f64::from(DSX_AMP),
+        DType::DFA | DType::DFB => f64::from(DFX_AMP),
+        DType::DDA | DType::DDB | DType::DXH | DType::NPY => DDX_AMP,
+        DType::DCB => f64::from(i8::MAX),
+        DType::DTB => DTB_AMP,
+        DType::DLB => f64::from(i32::MAX),
+        DType::DUB | DType::DAB | DType::WAV => f64::from(DSX_AMP),
+    }
+}
+
+/// Level holds the reference values that a shared scaling is derived from.
+pub(crate) struct Level {
+    peak: f64,
+    rms: f64,
+}
+
+impl Level {
+    pub(crate) fn measure<S: AsRef<[f64]>>(srcs: &[S]) -> Self {
+        let abs_src: Vec<f64> = srcs.iter().flat_map(|src| src.as_ref().iter().map(|x| x.abs())).collect();
+        let peak = max_f64s(&abs_src);
+        let rms = rms_f64s(&abs_src);
+        Level { peak, rms }
+    }
+
+    pub(crate) fn is_silent(&self) -> bool {
+        self.peak == 0.
+    }
+}
+
+/// NonFinitePolicy describes what to do with NaN or infinite input samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonFinitePolicy {
+    /// Error fails with `Error::NonFinite` at the first bad sample.
+    #[default]
+    Error,
+    /// ReplaceWithZero writes zeros instead and reports the indices in `WriteReport::replaced`.
+    ReplaceWithZero,
+}
+
+/// SilencePolicy describes what a normalizing scaling does with a signal that is all zeros.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SilencePolicy {
+    /// Error fails with `Error::Silent`.
+    Error,
+    /// Zeros writes the zeros as they are.
+    #[default]
+    Zeros,
+}
+
+/// WriteOptions controls how write_file_with and write_to_with encode the data.
+#[derive(Debug, Clone)]
+pub struct WriteOptions {
+    pub scaling: Scaling,
+    /// quantization is used when the data is written as one of the integer types.
+    pub quantization: Quantization,
+    pub non_finite: NonFinitePolicy,
+    pub silence: SilencePolicy,
+    /// endian is the byte order of DSB/DFB/DDB data.
+    pub endian: Endian,
+    /// channels is the number of interleaved channels of the data.
+    /// The text formats write one frame per line with the channels as columns.
+    pub channels: u16,
+}
+
+impl Default for WriteOptions {
+    fn default() -> Self {
+        WriteOptions {
+            scaling: Scaling::default(),
+            quantization: Quantization::default(),
+            non_finite: NonFinitePolicy::default(),
+            silence: SilencePolicy::default(),
+            endian: Endian::default(),
+            channels: 1,
+        }
+    }
+}
+
+impl WriteOptions {
+    /// new returns the options used by write_file.
+    pub fn new() -> Self {
+        WriteOptions::default()
+    }
+
+    /// with_scaling returns the options with `scaling` applied to the data.
+    pub fn with_scaling(mut self, scaling: Scaling) -> Self {
+        self.scaling = scaling;
+        self
+    }
+
+    /// with_quantization returns the options with `quantization` used for the integer types.
+    pub fn with_quantization(mut self, quantization: Quantization) -> Self {
+        self.quantization = quantization;
+        self
+    }
+
+    /// with_non_finite returns the options with the specified policy for NaN/Inf samples.
+    pub fn with_non_finite(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite = policy;
+        self
+    }
+
+    /// with_silence returns the options with the specified policy for silent signals.
+    pub fn with_silence(mut self, policy: SilencePolicy) -> Self {
+        self.silence = policy;
+        self
+    }
+
+    /// with_endian returns the options with the specified byte order.
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// with_channels returns the options with the specified number of interleaved channels.
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// check_finite applies the non-finite policy to `src`.
+    /// It returns the samples to be written and the indices of the replaced samples.
+    pub(crate) fn check_finite(&self, src: &[f64]) -> Result<(Vec<f64>, Vec<usize>), Error> {
+        let mut ret = src.to_vec();
+        let mut replaced: Vec<usize> = Vec::new();
+        for (index, x) in ret.iter_mut().enumerate() {
+            if x.is_finite() {
+                continue;
+            }
+            match self.non_finite {
+                NonFinitePolicy::Error => return Err(Error::NonFinite { index, value: *x }),
+                NonFinitePolicy::ReplaceWithZero => {
+                    *x = 0.;
+                    replaced.push(index);
+                }
+            }
+        }
+        Ok((ret, replaced))
+    }
+
+    /// check_channels reports whether `len` samples make whole frames of the channel count.
+    pub(crate) fn check_channels(&self, len: usize) -> Result<(), Error> {
+        Metadata::new().with_channels(self.channels).validate(len)
+    }
+
+    /// check_level applies the channel, non-finite and silence checks to `src` without
+    /// copying it, and returns its level as if the replaced samples were zeros.
+    pub(crate) fn check_level(&self, src: &[f64]) -> Result<Level, Error> {
+        self.check_channels(src.len())?;
+        let (mut peak, mut sum) = (0f64, 0f64);
+        for (index, &x) in src.iter().enumerate() {
+            if !x.is_finite() {
+                if self.non_finite == NonFinitePolicy::Error {
+                    return Err(Error::NonFinite { index, value: x });
+                }
+                continue;
+            }
+            peak = peak.max(x.abs());
+            sum += x * x;
+        }
+        let level = Level { peak, rms: (sum / src.len() as f64).sqrt() };
+        self.check_silence(&level)?;
+        Ok(level)
+    }
+
+    /// check_silence applies the silence policy to signals of the specified level.
+    pub(crate) fn check_silence(&self, level: &Level) -> Result<(), Error> {
+        if self.scaling.normalizes() && level.is_silent() && self.silence == SilencePolicy::Error {
+            return Err(Error::Silent);
+        }
+        Ok(())
+    }
+}
+
+/// WriteReport describes what happened to the data while it was written.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteReport {
+    /// clipped is the number of samples that exceeded the range of the data type.
+    pub clipped: u64,
+    /// replaced holds the indices of non-finite samples that were replaced with zero.
+    pub replaced: Vec<usize>,
+    /// silent is true if the signal was all zeros.
+    pub silent: bool,
+}
+
+/// ReadOptions controls how read_file_with and read_from_with decode the data.
+///
+/// The text formats (DSA/DFA/DDA) always accept CRLF line endings, whitespace around
+/// the values and blank lines. Lines starting with one of `comment_prefixes` are skipped.
+#[derive(Debug, Clone)]
+pub struct ReadOptions {
+    /// lenient makes the reader tolerate a binary length that is not a multiple
+    /// of the sample width, e.g. to recover a partially copied file.
+    pub lenient: bool,
+    /// comment_prefixes are the prefixes of comment lines in the text formats.
+    /// The default is `#` and `%` (MATLAB).
+    pub comment_prefixes: Vec<String>,
+    /// header_lines is the number of lines skipped at the beginning of the text formats,
+    /// e.g. 1 for a column name written by MATLAB or Excel.
+    pub header_lines: usize,
+    /// endian is the byte order of DSB/DFB/DDB data.
+    pub endian: Endian,
+    /// normalize_integers divides the samples of the integer types by their full scale
+    /// (e.g. 32768 for DSA/DSB) so that they are in [-1, 1).
+    /// It has no effect when the samples are read as i16.
+    pub normalize_integers: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions {
+            lenient: false,
+            comment_prefixes: vec!["#".to_string(), "%".to_string()],
+            header_lines: 0,
+            endian: Endian::default(),
+            normalize_integers: false,
+        }
+    }
+}
+
+impl ReadOptions {
+    /// new returns the options used by read_file.
+    pub fn new() -> Self {
+        ReadOptions::default()
+    }
+
+    /// with_lenient returns the options with the lenient mode switched on or off.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
+
+    /// with_comment_prefixes returns the options with the specified comment prefixes.
+    /// An empty slice disables comments.
+    pub fn with_comment_prefixes(mut self, prefixes: &[&str]) -> Self {
+        self.comment_prefixes = prefixes.iter().map(|p| p.to_string()).collect();
+        self
+    }
+
+    /// with_header_lines returns the options that skip the first `n` lines of text formats.
+    pub fn with_header_lines(mut self, n: usize) -> Self {
+        self.header_lines = n;
+        self
+    }
+
+    /// with_endian returns the options with the specified byte order.
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// with_normalize_integers returns the options that scale integer samples to [-1, 1).
+    pub fn with_normalize_integers(mut self, normalize: bool) -> Self {
+        self.normalize_integers = normalize;
+        self
+    }
+
+    /// is_comment reports whether a trimmed text line is a comment.
+    pub(crate) fn is_comment(&self, line: &str) -> bool {
+        self.comment_prefixes.iter().any(|p| !p.is_empty() && line.starts_with(p.as_str()))
+    }
+}
+
+/// ReadWarning is a problem that was tolerated while reading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReadWarning {
+    /// TrailingBytes means that the last `trailing` of `len` bytes did not form a whole sample
+    /// of `width` bytes and were ignored.
+    TrailingBytes { len: u64, trailing: usize, width: u32 },
+}
+
+impl fmt::Display for ReadWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ReadWarning::TrailingBytes { len, trailing, width } => write!(
+                f,
+                "ignored {} trailing bytes: length {} is not a multiple of the sample width {}",
+                trailing, len, width
+            ),
+        }
+    }
+}
+
+/// ReadReport describes what happened to the data while it was read.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReadReport {
+    pub warnings: Vec<ReadWarning>,
+}
+
+fn rms_f64s(src: &[f64]) -> f64 {
+    let sum: f64 = src.iter().map(|x| x * x).sum();
+    (sum / src.len() as f64).sqrt()
+}
+
+fn scale_f64s(src: &[f64], reference: f64, amp: f64) -> Vec<f64> {
+    if reference == 0. {
+        return vec![0.; src.len()];
+    }
+    src.iter().map(|x| x / reference * amp).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_scaling() {
+        let src: Vec<f64> = vec![5., -2., 4., -3.];
+        assert_eq!(Scaling::Raw.apply(&src, DType::DDB), src);
+        assert_eq!(Scaling::Peak(1.).apply(&src, DType::DDB), vec![1., -0.4, 0.8, -0.6]);
+        assert_eq!(Scaling::Gain(2.).apply(&src, DType::DDB), vec![10., -4., 8., -6.]);
+        assert_eq!(Scaling::Auto.apply(&src, DType::DDB), vec![10000., -4000., 8000., -6000.]);
+
+        let src: Vec<f64> = vec![3., -3., 3., -3.];
+        assert_eq!(Scaling::Rms(1.).apply(&src, DType::DDB), vec![1., -1., 1., -1.]);
+    }
+
+    #[test]
+    fn test_apply_jointly() {
+        let l: Vec<f64> = vec![2., -1., 0.5];
+        let r: Vec<f64> = vec![1., -0.5, 0.25];
+        let out = Scaling::Auto.apply_jointly(&[&l, &r], DType::DDB);
+        assert_eq!(out, vec![vec![10000., -5000., 2500.], vec![5000., -2500., 1250.]]);
+
+        let out = Scaling::Peak(1.).apply_jointly(&[l.clone(), r.clone()], DType::DDB);
+        assert_eq!(out, vec![vec![1., -0.5, 0.25], vec![0.5, -0.25, 0.125]]);
+
+        // A single signal is scaled in the same way as apply.
+        let out = Scaling::Auto.apply_jointly(&[&l], DType::DDB);
+        assert_eq!(out[0], Scaling::Auto.apply(&l, DType::DDB));
+    }
+
+    #[test]
+    fn test_write_quantization() {
+        let src: Vec<f64> = vec![1.6, -1.6, 40000.];
+        let options = WriteOptions::new()
+            .with_scaling(Scaling::Raw)
+            .with_quantization(Quantization::new().with_rounding(Rounding::Nearest));
+        let mut buf: Vec<u8> = Vec::new();
+        let report = write_to_with(&mut buf, DType::DSB, &src, &options).unwrap();
+        assert_eq!(report.clipped, 1);
+        assert_eq!(read_bytes(&buf, DType::DSB).unwrap(), vec![2., -2., 32767.]);
+    }
+
+    #[test]
+    fn test_non_finite() {
+        let src: Vec<f64> = vec![1., f64::NAN, -1., f64::INFINITY];
+        match write_bytes(DType::DDB, &src) {
+            Err(Error::NonFinite { index, .. }) => assert_eq!(index, 1),
+            r => panic!("want Error::NonFinite, got {:?}", r),
+        }
+
+        let options = WriteOptions::new().with_non_finite(NonFinitePolicy::ReplaceWithZero);
+        let mut buf: Vec<u8> = Vec::new();
+        let report = write_to_with(&mut buf, DType::DDB, &src, &options).unwrap();
+        assert_eq!(report.replaced, vec![1, 3]);
+        assert_eq!(read_bytes(&buf, DType::DDB).unwrap(), vec![10000., 0., -10000., 0.]);
+    }
+
+    #[test]
+    fn test_silence() {
+        let src: Vec<f64> = vec![0.; 4];
+        for &dtype in &[DType::DSB, DType::DFB, DType::DDB, DType::DDA] {
+            let buf = write_bytes(dtype, &src).unwrap();
+            assert_eq!(read_bytes(&buf, dtype).unwrap(), src);
+        }
+        assert_eq!(Scaling::Rms(0.1).apply(&src, DType::DDA), src);
+        assert_eq!(Scaling::Peak(0.1).apply(&src, DType::DDA), src);
+
+        let options = WriteOptions::new().with_silence(SilencePolicy::Error);
+        let mut buf: Vec<u8> = Vec::new();
+        let err = write_to_with(&mut buf, DType::DDB, &src, &options).unwrap_err();
+        assert!(matches!(err, Error::Silent));
+
+        // Raw does not normalize, so silence is not an error.
+        let options = options.with_scaling(Scaling::Raw);
+        let report = write_to_with(&mut buf, DType::DDB, &src, &options).unwrap();
+        assert!(report.silent);
+    }
+
+    #[test]
+    fn test_write_raw_round_trip() {
+        let src: Vec<f64> = vec![0.5, -0.25, 1e-3, -3.];
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+        for &dtype in &[DType::DDA, DType::DDB] {
+            let mut buf: Vec<u8> = Vec::new();
+            write_to_with(&mut buf, dtype, &src, &options).unwrap();
+            assert_eq!(read_bytes(&buf, dtype).unwrap(), src);
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Quantization of f64 samples to the integers of DSA/DSB and the other integer types.
+
+/// Rounding describes how a sample is mapped to the integer grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// Truncate rounds toward zero like `as i16`.
+    /// It is kept for output that must match older files bit for bit.
+    Truncate,
+    /// Nearest rounds to the nearest integer, half away from zero.
+    /// This is what write_file does.
+    #[default]
+    Nearest,
+}
+
+/// Dither describes the noise added to a sample before it is rounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Dither {
+    #[default]
+    None,
+    /// Tpdf adds triangular noise with a peak of 1 LSB.
+    Tpdf,
+    /// NoiseShaped adds TPDF noise and feeds back the quantization error,
+    /// which moves the error power towards high frequencies.
+    NoiseShaped,
+}
+
+/// Quantization configures how samples are converted to integers.
+/// The seed makes the dither reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Quantization {
+    pub rounding: Rounding,
+    pub dither: Dither,
+    pub seed: u64,
+}
+
+impl Quantization {
+    /// new returns the quantization used by write_file.
+    pub fn new() -> Self {
+        Quantization::default()
+    }
+
+    /// with_rounding returns the quantization with the specified rounding.
+    pub fn with_rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = rounding;
+        self
+    }
+
+    /// with_dither returns the quantization with the specified dither and seed.
+    pub fn with_dither(mut self, dither: Dither, seed: u64) -> Self {
+        self.dither = dither;
+        self.seed = seed;
+        self
+    }
+}
+
+/// Quantizer converts a stream of f64 samples to integers.
+/// Samples outside the range of the integer type saturate and are counted.
+#[derive(Debug, Clone)]
+pub struct Quantizer {
+    config: Quantization,
+    rng: Rng,
+    error: f64,
+    clipped: u64,
+}
+
+impl Quantizer {
+    /// new returns a quantizer with the specified configuration.
+    pub fn new(config: Quantization) -> Self {
+        Quantizer { config, rng: Rng::new(config.seed), error: 0., clipped: 0 }
+    }
+
+    /// quantize converts a single sample to i16.
+    pub fn quantize(&mut self, x: f64) -> i16 {
+        self.quantize_bits(x, 16) as i16
+    }
+
+    /// quantize_bits converts a single sample to a signed integer of `bits` bits.
+    /// `bits` is at most 32.
+    pub fn quantize_bits(&mut self, x: f64, bits: u32) -> i32 {
+        debug_assert!((1..=32).contains(&bits), "invalid bit width {}", bits);
+        let x = match self.config.dither {
+            Dither::None => x,
+            Dither::Tpdf => x + self.rng.tpdf(),
+            Dither::NoiseShaped => x - self.error + self.rng.tpdf(),
+        };
+        let y = match self.config.rounding {
+            Rounding::Truncate => x.trunc(),
+            Rounding::Nearest => x.round(),
+        };
+        if self.config.dither == Dither::NoiseShaped {
+            self.error = y - x;
+        }
+
+        let max = ((1i64 << (bits - 1)) - 1) as f64;
+        let min = -((1i64 << (bits - 1)) as f64);
+        if y > max || y < min {
+            self.clipped += 1;
+        }
+        // NaN is not clamped and casts to 0, like `as i16` does.
+        y.clamp(min, max) as i32
+    }
+
+    /// quantize_all converts all samples of `src`.
+    pub fn quantize_all(&mut self, src: &[f64]) -> Vec<i16> {
+        src.iter().map(|&x| self.quantize(x)).collect()
+    }
+
+    /// clipped returns the number of samples that have been clipped so far.
+    pub fn clipped(&self) -> u64 {
+        self.clipped
+    }
+}
+
+/// Rng is a small xorshift64* generator so that dither does not need an extra dependency.
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // splitmix64 spreads the seed so that small seeds (including 0) give a valid state.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^= z >> 31;
+        Rng(if z == 0 { 1 } else { z })
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// uniform returns a value in [0, 1).
+    fn uniform(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// tpdf returns a value in (-1, 1) with a triangular distribution.
+    fn tpdf(&mut self) -> f64 {
+        self.uniform() - self.uniform()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_rounding() {
+        let src: Vec<f64> = vec![1.4, 1.6, -1.4, -1.6, 0.5, -0.5];
+        let mut q = Quantizer::new(Quantization::new());
+        assert_eq!(q.quantize_all(&src), vec![1, 2, -1, -2, 1, -1]);
+        let mut q = Quantizer::new(Quantization::new().with_rounding(Rounding::Truncate));
+        assert_eq!(q.quantize_all(&src), vec![1, 1, -1, -1, 0, 0]);
+    }
+
+    #[test]
+    fn test_clipping() {
+        let src: Vec<f64> = vec![40000., -40000., 32767., -32768., 0.];
+        let mut q = Quantizer::new(Quantization::new());
+        assert_eq!(q.quantize_all(&src), vec![32767, -32768, 32767, -32768, 0]);
+        assert_eq!(q.clipped(), 2);
+
+        let mut q = Quantizer::new(Quantization::new());
+        assert_eq!(q.quantize_bits(200., 8), 127);
+        assert_eq!(q.quantize_bits(-1e7, 24), -8388608);
+        assert_eq!(q.quantize_bits(3e9, 32), i32::MAX);
+        assert_eq!(q.quantize_bits(-123456.7, 32), -123457);
+        assert_eq!(q.clipped(), 3);
+    }
+
+    #[test]
+    fn test_dither() {
+        let src: Vec<f64> = vec![0.25; 10000];
+        for &dither in &[Dither::Tpdf, Dither::NoiseShaped] {
+            let config = Quantization::new().with_rounding(Rounding::Nearest).with_dither(dither, 42);
+            let a = Quantizer::new(config).quantize_all(&src);
+            let b = Quantizer::new(config).quantize_all(&src);
+            assert_eq!(a, b);
+            assert!(a.iter().all(|&x| (-1..=2).contains(&x)));
+
+            // Dither removes the rounding bias: the mean stays close to the input.
+            let mean = a.iter().map(|&x| f64::from(x)).sum::<f64>() / a.len() as f64;
+            assert!((mean - 0.25).abs() < 0.05, "mean = {}", mean);
+        }
+    }
+}
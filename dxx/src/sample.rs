@@ -0,0 +1,276 @@
+//! Native-typed access to .DXX files.
+//!
+//! read_file and write_file always go through f64. The functions in this module
+//! keep the samples in their native type (i16, f32 or f64) and only convert
+//! when the data type of the file differs from the requested type.
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::endian::Endian;
+use crate::error::{Error, Result};
+use crate::metadata::remove_metadata;
+use crate::options::{ReadOptions, ReadReport, ReadWarning, Scaling, WriteOptions};
+use crate::{len_file, read_file_with, write_file_with, DType};
+
+/// integer_full_scale returns the value that samples of an integer type are divided by
+/// when they are normalized to [-1, 1), or None for the floating-point types.
+pub(crate) fn integer_full_scale(dtype: DType) -> Option<f64> {
+    match dtype {
+        DType::DSA | DType::DSB | DType::DUB | DType::DAB => Some(32768.),
+        DType::DCB => Some(128.),
+        DType::DTB => Some(8388608.),
+        DType::DLB => Some(2147483648.),
+        DType::DFA | DType::DDA | DType::DFB | DType::DDB | DType::DXH | DType::WAV | DType::NPY => None,
+    }
+}
+
+/// Sample is a sample type that DXX data is stored as.
+/// It is implemented for i16 (DSA/DSB), f32 (DFA/DFB) and f64 (DDA/DDB).
+pub trait Sample: Copy + Default + PartialEq + fmt::Debug + fmt::Display + 'static {
+    /// TEXT is the text data type that stores this type.
+    const TEXT: DType;
+    /// BINARY is the binary data type that stores this type.
+    const BINARY: DType;
+
+    /// to_f64 converts the sample to f64 without loss.
+    fn to_f64(self) -> f64;
+
+    /// from_f64 converts a f64 to the sample type.
+    /// Integers are truncated and saturate at the bounds of the type.
+    fn from_f64(x: f64) -> Self;
+
+    /// read_into fills `dst` with binary samples of the specified byte order.
+    fn read_into<R: Read>(src: &mut R, dst: &mut [Self], endian: Endian) -> io::Result<()>;
+
+    /// write_one writes a binary sample of the specified byte order.
+    fn write_one<W: Write>(self, dst: &mut W, endian: Endian) -> io::Result<()>;
+
+    /// is_native reports whether `dtype` stores samples of this type.
+    fn is_native(dtype: DType) -> bool {
+        dtype == Self::TEXT || dtype == Self::BINARY
+    }
+}
+
+impl Sample for i16 {
+    const TEXT: DType = DType::DSA;
+    const BINARY: DType = DType::DSB;
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+
+    fn from_f64(x: f64) -> Self {
+        x as i16
+    }
+
+    fn read_into<R: Read>(src: &mut R, dst: &mut [Self], endian: Endian) -> io::Result<()> {
+        match endian {
+            Endian::Little => src.read_i16_into::<LittleEndian>(dst),
+            Endian::Big => src.read_i16_into::<BigEndian>(dst),
+        }
+    }
+
+    fn write_one<W: Write>(self, dst: &mut W, endian: Endian) -> io::Result<()> {
+        match endian {
+            Endian::Little => dst.write_i16::<LittleEndian>(self),
+            Endian::Big => dst.write_i16::<BigEndian>(self),
+        }
+    }
+}
+
+impl Sample for f32 {
+    const TEXT: DType = DType::DFA;
+    const BINARY: DType = DType::DFB;
+
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
+
+    fn from_f64(x: f64) -> Self {
+        x as f32
+    }
+
+    fn read_into<R: Read>(src: &mut R, dst: &mut [Self], endian: Endian) -> io::Result<()> {
+        match endian {
+            Endian::Little => src.read_f32_into::<LittleEndian>(dst),
+            Endian::Big => src.read_f32_into::<BigEndian>(dst),
+        }
+    }
+
+    fn write_one<W: Write>(self, dst: &mut W, endian: Endian) -> io::Result<()> {
+        match endian {
+            Endian::Little => dst.write_f32::<LittleEndian>(self),
+            Endian::Big => dst.write_f32::<BigEndian>(self),
+        }
+    }
+}
+
+impl Sample for f64 {
+    const TEXT: DType = DType::DDA;
+    const BINARY: DType = DType::DDB;
+
+    fn to_f64(self) -> f64 {
+        self
+    }
+
+    fn from_f64(x: f64) -> Self {
+        x
+    }
+
+    fn read_into<R: Read>(src: &mut R, dst: &mut [Self], endian: Endian) -> io::Result<()> {
+        match endian {
+            Endian::Little => src.read_f64_into::<LittleEndian>(dst),
+            Endian::Big => src.read_f64_into::<BigEndian>(dst),
+        }
+    }
+
+    fn write_one<W: Write>(self, dst: &mut W, endian: Endian) -> io::Result<()> {
+        match endian {
+            Endian::Little => dst.write_f64::<LittleEndian>(self),
+            Endian::Big => dst.write_f64::<BigEndian>(self),
+        }
+    }
+}
+
+/// read_file_as reads .DXX file as samples of type T.
+/// A binary file that already stores T is read without any conversion.
+pub fn read_file_as<T: Sample, P: AsRef<Path>>(filename: P) -> Result<Vec<T>> {
+    let (ret, _) = read_file_as_with(filename, &ReadOptions::default())?;
+    Ok(ret)
+}
+
+/// read_file_as_with reads .DXX file as samples of type T with the specified options.
+/// With `ReadOptions::normalize_integers`, integer data read as f32 or f64 is scaled to [-1, 1).
+/// Like read_file_with, it reports the trailing bytes that a lenient read ignored.
+pub fn read_file_as_with<T: Sample, P: AsRef<Path>>(filename: P, options: &ReadOptions) -> Result<(Vec<T>, ReadReport)> {
+    let filename = filename.as_ref();
+    let dtype = DType::from_path(filename)?;
+    if dtype != T::BINARY {
+        let mut options = options.clone();
+        // Integers read as integers keep their raw values.
+        if T::BINARY.is_integer() && dtype.is_integer() {
+            options.normalize_integers = false;
+        }
+        let (data, report) = read_file_with(filename, &options)?;
+        return Ok((data.into_iter().map(T::from_f64).collect(), report));
+    }
+
+    let width = u64::from(dtype.byte_width());
+    let len = len_file(filename)?;
+    let mut report = ReadReport::default();
+    if !len.is_multiple_of(width) {
+        if !options.lenient {
+            return Err(Error::SizeMismatch { path: None, len, dtype }.with_path(filename));
+        }
+        report.warnings.push(ReadWarning::TrailingBytes { len, trailing: (len % width) as usize, width: dtype.byte_width() });
+    }
+    let f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let mut ret: Vec<T> = vec![T::default(); (len / width) as usize];
+    T::read_into(&mut BufReader::new(f), &mut ret, options.endian).map_err(|e| Error::from(e).with_path(filename))?;
+    Ok((ret, report))
+}
+
+/// write_file_from writes samples of type T to .DXX file as they are.
+/// A binary file that stores T is written without any conversion.
+pub fn write_file_from<T: Sample, P: AsRef<Path>>(filename: P, src: &[T]) -> Result<()> {
+    write_file_from_with(filename, src, &WriteOptions::new().with_scaling(Scaling::Raw))
+}
+
+/// write_file_from_with writes samples of type T to .DXX file with the specified options.
+/// The native fast path is taken only when no scaling is requested and every sample is finite;
+/// otherwise the samples go through write_file_with and its non-finite policy.
+pub fn write_file_from_with<T: Sample, P: AsRef<Path>>(filename: P, src: &[T], options: &WriteOptions) -> Result<()> {
+    let filename = filename.as_ref();
+    let dtype = DType::from_path(filename)?;
+    if dtype != T::BINARY || options.scaling != Scaling::Raw || src.iter().any(|x| !x.to_f64().is_finite()) {
+        let data: Vec<f64> = src.iter().map(|x| x.to_f64()).collect();
+        write_file_with(filename, &data, options)?;
+        return Ok(());
+    }
+
+    // Raw does not normalize, so the silence policy does not apply; the channels are
+    // checked before the file is created, as write_file_with does.
+    options.check_channels(src.len())?;
+    let f = File::create(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let mut writer = BufWriter::new(f);
+    for &x in src {
+        x.write_one(&mut writer, options.endian).map_err(|e| Error::from(e).with_path(filename))?;
+    }
+    writer.flush().map_err(|e| Error::from(e).with_path(filename))?;
+    remove_metadata(filename)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::*;
+
+    #[test]
+    fn test_native_round_trip() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+
+        let src: Vec<i16> = vec![1, -2, i16::MAX, i16::MIN];
+        write_file_from(dir.join("a.DSB"), &src).unwrap();
+        assert_eq!(read_file_as::<i16, _>(dir.join("a.DSB")).unwrap(), src);
+        write_file_from(dir.join("a.DSA"), &src).unwrap();
+        assert_eq!(read_file_as::<i16, _>(dir.join("a.DSA")).unwrap(), src);
+
+        let src: Vec<f32> = vec![0.1, -0.25, 1e-7];
+        write_file_from(dir.join("a.DFB"), &src).unwrap();
+        assert_eq!(read_file_as::<f32, _>(dir.join("a.DFB")).unwrap(), src);
+        write_file_from(dir.join("a.DFA"), &src).unwrap();
+        assert_eq!(read_file_as::<f32, _>(dir.join("a.DFA")).unwrap(), src);
+        // Converted on the fly when the types differ.
+        assert_eq!(read_file_as::<f64, _>(dir.join("a.DFB")).unwrap(), vec![0.1f32 as f64, -0.25, 1e-7f32 as f64]);
+    }
+
+    #[test]
+    fn test_normalize_integers() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<i16> = vec![16384, -32768, 0];
+        write_file_from(dir.join("a.DSB"), &src).unwrap();
+
+        let options = ReadOptions::new().with_normalize_integers(true);
+        assert_eq!(read_file_as_with::<f32, _>(dir.join("a.DSB"), &options).unwrap().0, vec![0.5, -1., 0.]);
+        assert_eq!(read_file_with(dir.join("a.DSB"), &options).unwrap().0, vec![0.5, -1., 0.]);
+        // Integer output keeps the raw values.
+        assert_eq!(read_file_as_with::<i16, _>(dir.join("a.DSB"), &options).unwrap().0, src);
+        write_file_from(dir.join("a.DSA"), &src).unwrap();
+        assert_eq!(read_file_as_with::<i16, _>(dir.join("a.DSA"), &options).unwrap().0, src);
+        assert_eq!(read_file_as_with::<f64, _>(dir.join("a.DSA"), &options).unwrap().0, vec![0.5, -1., 0.]);
+    }
+
+    #[test]
+    fn test_native_checks() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let filename = dir.join("a.DDB");
+        write_file_from(&filename, &[1f64, 2.]).unwrap();
+
+        // A rejected write keeps the existing file.
+        assert!(matches!(write_file_from(&filename, &[1f64, f64::NAN]), Err(Error::NonFinite { index: 1, .. })));
+        let options = WriteOptions::new().with_scaling(Scaling::Raw).with_channels(2);
+        assert!(matches!(write_file_from_with(&filename, &[1f64, 2., 3.], &options), Err(Error::ChannelMismatch { len: 3, channels: 2, .. })));
+        assert_eq!(read_file_as::<f64, _>(&filename).unwrap(), vec![1., 2.]);
+
+        let options = WriteOptions::new().with_scaling(Scaling::Raw).with_non_finite(NonFinitePolicy::ReplaceWithZero);
+        write_file_from_with(&filename, &[f32::INFINITY, 2.], &options).unwrap();
+        assert_eq!(read_file_as::<f64, _>(&filename).unwrap(), vec![0., 2.]);
+
+        // The trailing bytes ignored by a lenient read are reported.
+        fs::write(dir.join("b.DSB"), [1u8, 0, 2]).unwrap();
+        assert!(matches!(read_file_as::<i16, _>(dir.join("b.DSB")), Err(Error::SizeMismatch { len: 3, .. })));
+        let (data, report) = read_file_as_with::<i16, _>(dir.join("b.DSB"), &ReadOptions::new().with_lenient(true)).unwrap();
+        assert_eq!(data, vec![1]);
+        assert_eq!(report.warnings, vec![ReadWarning::TrailingBytes { len: 3, trailing: 1, width: 2 }]);
+    }
+}
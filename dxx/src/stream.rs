@@ -0,0 +1,891 @@
+//! Streaming access to .DXX files.
+//!
+//! `DxxReader` and `DxxWriter` process one sample at a time so that long signals
+//! do not have to be held in memory as a whole.
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io;
+use std::io::{BufReader, BufWriter, ErrorKind, Lines, SeekFrom};
+use std::path::Path;
+
+use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
+
+use crate::companding::{encode_alaw, encode_mulaw};
+use crate::dxh::DxhHeader;
+use crate::endian::{decode_sample, Endian};
+use crate::options::{ReadOptions, ReadWarning};
+use crate::quantize::{Quantization, Quantizer};
+use crate::sample::integer_full_scale;
+use crate::error::{Error, Result};
+use crate::npy::NpyHeader;
+use crate::wav::WavSpec;
+use crate::DType;
+
+enum Source<R: Read> {
+    Text(Lines<BufReader<R>>),
+    Binary(BufReader<R>),
+}
+
+/// DxxReader reads samples of a .DXX stream one by one.
+/// Every sample is widened to f64 regardless of the data type.
+/// A binary stream whose length is not a multiple of the sample width fails with
+/// `Error::SizeMismatch` unless the reader is lenient.
+/// Text streams are parsed as described in `ReadOptions`.
+/// A text line may hold several whitespace-separated columns, which are read as
+/// interleaved channels; every line must have the same number of columns.
+/// A DXH stream starts with a header, which sets the data type and the byte order.
+/// A WAV stream is read up to the end of its `data` chunk, and an NPY stream up to the end
+/// of its array; the columns of a 2-D array are interleaved channels.
+pub struct DxxReader<R: Read> {
+    src: Source<R>,
+    dtype: DType,
+    header: Option<DxhHeader>,
+    wav_spec: Option<WavSpec>,
+    npy_header: Option<NpyHeader>,
+    pending_header: bool,
+    remaining: Option<u64>,
+    pos: u64,
+    columns: Option<usize>,
+    pending: VecDeque<f64>,
+    options: ReadOptions,
+    warnings: Vec<ReadWarning>,
+    done: bool,
+}
+
+impl DxxReader<File> {
+    /// open opens the specified .DXX file.
+    /// The data type is determined from the filename extension.
+    pub fn open<P: AsRef<Path>>(filename: P) -> Result<Self> {
+        let filename = filename.as_ref();
+        let dtype = DType::from_path(filename)?;
+        let f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+        let mut reader = DxxReader::new(f, dtype);
+        reader.read_header().map_err(|e| e.with_path(filename))?;
+        Ok(reader)
+    }
+}
+
+impl<R: Read> DxxReader<R> {
+    /// new wraps a reader that yields data of the specified type.
+    pub fn new(src: R, dtype: DType) -> Self {
+        let reader = BufReader::new(src);
+        let src = match dtype {
+            DType::DSA | DType::DFA | DType::DDA => Source::Text(reader.lines()),
+            _ => Source::Binary(reader),
+        };
+        DxxReader {
+            src,
+            dtype,
+            header: None,
+            wav_spec: None,
+            npy_header: None,
+            pending_header: dtype.is_container(),
+            remaining: None,
+            pos: 0,
+            columns: None,
+            pending: VecDeque::new(),
+            options: ReadOptions::default(),
+            warnings: Vec::new(),
+            done: false,
+        }
+    }
+
+    /// with_options sets the options of the reader.
+    pub fn with_options(mut self, options: ReadOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// with_lenient makes the reader ignore trailing bytes shorter than a sample.
+    /// The ignored bytes are recorded as a warning instead of failing.
+    pub fn with_lenient(mut self, lenient: bool) -> Self {
+        self.options.lenient = lenient;
+        self
+    }
+
+    /// with_endian sets the byte order of DSB/DFB/DDB samples.
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.options.endian = endian;
+        self
+    }
+
+    /// warnings returns the problems that were tolerated so far.
+    pub fn warnings(&self) -> &[ReadWarning] {
+        &self.warnings
+    }
+
+    /// into_warnings consumes the reader and returns the tolerated problems.
+    pub fn into_warnings(self) -> Vec<ReadWarning> {
+        self.warnings
+    }
+
+    /// dtype returns the data type of the stream.
+    /// For a DXH, WAV or NPY stream it is the payload type once the header has been read.
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
+
+    /// columns returns the number of columns of a text stream once the first line
+    /// with data has been read, or of a 2-D NPY array once its header has been read.
+    /// It is None for other binary streams.
+    pub fn columns(&self) -> Option<usize> {
+        self.columns
+    }
+
+    /// header returns the header of a DXH stream once it has been read.
+    /// `open` reads it right away; otherwise it is read with the first sample.
+    pub fn header(&self) -> Option<&DxhHeader> {
+        self.header.as_ref()
+    }
+
+    /// wav_spec returns the spec of a WAV stream once its header has been read.
+    /// `open` reads it right away; otherwise it is read with the first sample.
+    pub fn wav_spec(&self) -> Option<&WavSpec> {
+        self.wav_spec.as_ref()
+    }
+
+    /// npy_header returns the header of an NPY stream once it has been read.
+    /// `open` reads it right away; otherwise it is read with the first sample.
+    pub fn npy_header(&self) -> Option<&NpyHeader> {
+        self.npy_header.as_ref()
+    }
+
+    fn read_header(&mut self) -> Result<()> {
+        if !self.pending_header {
+            return Ok(());
+        }
+        self.pending_header = false;
+        let reader = match self.src {
+            Source::Binary(ref mut reader) => reader,
+            Source::Text(_) => unreachable!("read_header on a text stream"),
+        };
+        if self.dtype == DType::WAV {
+            let (spec, _, len) = WavSpec::read(reader)?;
+            self.dtype = spec.dtype;
+            self.remaining = Some(len);
+            self.wav_spec = Some(spec);
+            return Ok(());
+        }
+        if self.dtype == DType::NPY {
+            let (header, _) = NpyHeader::read(reader)?;
+            let width = header.dtype.byte_width() as usize;
+            self.dtype = header.dtype;
+            self.remaining = Some((header.len() * width) as u64);
+            if header.shape.len() == 2 {
+                self.columns = Some(header.channels());
+            }
+            if header.fortran_order && header.channels() > 1 {
+                // A Fortran-order array is stored channel by channel, so it is read
+                // as a whole and interleaved.
+                // The buffer grows with the bytes actually read, so a bogus shape cannot allocate much.
+                let mut buf: Vec<u8> = Vec::new();
+                reader.take((header.len() * width) as u64).read_to_end(&mut buf)?;
+                if buf.len() != header.len() * width {
+                    return Err(Error::InvalidNpy("truncated".to_string()));
+                }
+                let samples: Vec<f64> = buf.chunks_exact(width).map(|b| decode_sample(b, header.dtype, header.endian)).collect();
+                let (frames, channels) = (header.shape[0], header.channels());
+                for i in 0..frames {
+                    self.pending.extend((0..channels).map(|c| samples[c * frames + i]));
+                }
+                self.pos += buf.len() as u64;
+                self.remaining = Some(0);
+            }
+            self.npy_header = Some(header);
+            return Ok(());
+        }
+        let (header, _) = DxhHeader::read(reader)?;
+        self.dtype = header.dtype;
+        self.header = Some(header);
+        Ok(())
+    }
+
+    /// endian returns the byte order of the samples, which a header takes precedence for.
+    fn endian(&self) -> Endian {
+        match (&self.header, &self.wav_spec, &self.npy_header) {
+            (Some(header), _, _) => header.endian,
+            (None, Some(_), _) => Endian::Little,
+            (None, None, Some(header)) => header.endian,
+            (None, None, None) => self.options.endian,
+        }
+    }
+
+    /// read_chunk fills `buf` with the next samples and returns how many were read.
+    /// A return value smaller than `buf.len()` means the end of the stream was reached.
+    pub fn read_chunk(&mut self, buf: &mut [f64]) -> Result<usize> {
+        let mut n = 0;
+        while n < buf.len() {
+            match self.next() {
+                Some(x) => buf[n] = x?,
+                None => break,
+            }
+            n += 1;
+        }
+        Ok(n)
+    }
+
+    /// chunks turns the reader into an iterator over blocks of `size` samples.
+    /// The last block may be shorter than `size`.
+    pub fn chunks(self, size: usize) -> DxxChunks<R> {
+        assert!(size > 0, "chunk size must be positive");
+        DxxChunks { reader: self, size }
+    }
+
+    fn read_binary_sample(&mut self) -> Option<Result<f64>> {
+        if let Err(e) = self.read_header() {
+            self.done = true;
+            return Some(Err(e));
+        }
+        if let Some(x) = self.pending.pop_front() {
+            return Some(Ok(x));
+        }
+        let endian = self.endian();
+        let reader = match self.src {
+            Source::Binary(ref mut reader) => reader,
+            Source::Text(_) => unreachable!("read_binary_sample on a text stream"),
+        };
+        let width = self.dtype.byte_width() as usize;
+        // A WAV stream ends with its data chunk even if other chunks follow.
+        // An NPY stream ends with its array.
+        let want = self.remaining.map_or(width, |remaining| width.min(remaining as usize));
+        let mut buf = [0u8; 8];
+        let mut filled = 0;
+        while filled < want {
+            match reader.read(&mut buf[filled..want]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
+                }
+            }
+        }
+        if filled == 0 {
+            return None;
+        }
+        self.pos += filled as u64;
+        if let Some(ref mut remaining) = self.remaining {
+            *remaining -= filled as u64;
+        }
+        if filled < width {
+            self.done = true;
+            let len = self.pos;
+            if self.options.lenient {
+                self.warnings.push(ReadWarning::TrailingBytes { len, trailing: filled, width: width as u32 });
+                return None;
+            }
+            return Some(Err(Error::SizeMismatch { path: None, len, dtype: self.dtype }));
+        }
+
+        Some(Ok(decode_sample(&buf[..width], self.dtype, endian)))
+    }
+}
+
+impl<R: Read> Iterator for DxxReader<R> {
+    type Item = Result<f64>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let x = self.next_raw()?;
+        match integer_full_scale(self.dtype) {
+            Some(scale) if self.options.normalize_integers => Some(x.map(|x| x / scale)),
+            _ => Some(x),
+        }
+    }
+}
+
+impl<R: Read> DxxReader<R> {
+    fn next_raw(&mut self) -> Option<Result<f64>> {
+        match self.src {
+            Source::Text(ref mut lines) => loop {
+                if let Some(x) = self.pending.pop_front() {
+                    return Some(Ok(x));
+                }
+                let line = match lines.next()? {
+                    Ok(line) => line,
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e.into()));
+                    }
+                };
+                self.pos += 1;
+                let line_no = self.pos as usize;
+                if line_no <= self.options.header_lines {
+                    continue;
+                }
+                let got = match parse_text_line(&line, line_no, &self.options, &mut self.pending) {
+                    Ok(0) => continue,
+                    Ok(got) => got,
+                    Err(e) => return Some(Err(e)),
+                };
+                match self.columns {
+                    None => self.columns = Some(got),
+                    Some(want) if want != got => {
+                        self.pending.clear();
+                        return Some(Err(Error::ColumnMismatch { path: None, line: line_no, want, got }));
+                    }
+                    Some(_) => {}
+                }
+            },
+            Source::Binary(_) => self.read_binary_sample(),
+        }
+    }
+}
+
+/// parse_text_line parses the columns of a line of a text format into `dst`
+/// and returns the number of columns. Blank and comment lines have no columns.
+fn parse_text_line(line: &str, line_no: usize, options: &ReadOptions, dst: &mut VecDeque<f64>) -> Result<usize> {
+    // Excel writes a byte order mark at the beginning of UTF-8 files.
+    let line = if line_no == 1 { line.trim_start_matches('\u{feff}') } else { line };
+    let text = line.trim();
+    if text.is_empty() || options.is_comment(text) {
+        return Ok(0);
+    }
+    let mut n = 0;
+    for token in text.split_whitespace() {
+        match token.parse::<f64>() {
+            Ok(x) => dst.push_back(x),
+            Err(source) => {
+                dst.clear();
+                let offset = token.as_ptr() as usize - line.as_ptr() as usize;
+                let column = line[..offset].chars().count() + 1;
+                return Err(Error::Parse { path: None, line: line_no, column, text: token.to_string(), source });
+            }
+        }
+        n += 1;
+    }
+    Ok(n)
+}
+
+/// DxxChunks is an iterator over fixed-size blocks of samples.
+/// It is created by `DxxReader::chunks`.
+pub struct DxxChunks<R: Read> {
+    reader: DxxReader<R>,
+    size: usize,
+}
+
+impl<R: Read> Iterator for DxxChunks<R> {
+    type Item = Result<Vec<f64>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = vec![0.; self.size];
+        match self.reader.read_chunk(&mut buf) {
+            Ok(0) => None,
+            Ok(n) => {
+                buf.truncate(n);
+                Some(Ok(buf))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// DxxWriter writes samples to a .DXX stream incrementally.
+/// Samples are converted to the data type of the stream as they are,
+/// so any scaling has to be applied before pushing them.
+/// Samples of the integer types are converted by the quantizer of the writer;
+/// DUB/DAB samples are quantized to 16 bits and then companded.
+/// A DXH stream gets its header before the first sample; unless `with_header` is used,
+/// the payload is little-endian DDB.
+/// WAV and NPY streams need the number of their samples in the header, which is
+/// written before the first sample as well. Either declare the number with `with_len`,
+/// or create the writer with `new_seekable` so that `finish` patches the header;
+/// a WAV or NPY stream on a writer that cannot seek and without a declared length is
+/// refused with `Error::UnknownLength`, as it would have to be held in memory.
+/// Unless `with_wav_spec` is used, the payload of WAV is 16-bit PCM; unless
+/// `with_npy_header` is used, the payload of NPY is float64.
+/// Call `finish` to flush the buffered data and detect write errors.
+pub struct DxxWriter<W: Write> {
+    dst: BufWriter<W>,
+    dtype: DType,
+    header: Option<DxhHeader>,
+    wav_spec: Option<WavSpec>,
+    npy_header: Option<NpyHeader>,
+    /// seek moves the underlying writer; it is set by new_seekable.
+    seek: Option<fn(&mut W, SeekFrom) -> io::Result<u64>>,
+    /// declared_len is the number of samples declared by with_len.
+    declared_len: Option<u64>,
+    /// placeholder is the position and the length of a WAV or NPY header to be patched by finish.
+    placeholder: Option<(u64, u64)>,
+    started: bool,
+    len: u64,
+    channels: u16,
+    quantizer: Quantizer,
+    endian: Endian,
+}
+
+impl DxxWriter<File> {
+    /// create creates the specified .DXX file.
+    /// The data type is determined from the filename extension.
+    pub fn create<P: AsRef<Path>>(filename: P) -> Result<Self> {
+        let filename = filename.as_ref();
+        let dtype = DType::from_path(filename)?;
+        let f = File::create(filename).map_err(|e| Error::from(e).with_path(filename))?;
+        Ok(DxxWriter::new_seekable(f, dtype))
+    }
+}
+
+impl<W: Write + Seek> DxxWriter<W> {
+    /// new_seekable wraps a seekable writer that receives data of the specified type.
+    /// The header of a WAV or NPY stream is written with placeholder lengths and
+    /// patched by `finish`, so the number of samples need not be known in advance.
+    pub fn new_seekable(dst: W, dtype: DType) -> Self {
+        let mut writer = DxxWriter::new(dst, dtype);
+        writer.seek = Some(|w: &mut W, pos: SeekFrom| w.seek(pos));
+        writer
+    }
+}
+
+impl<W: Write> DxxWriter<W> {
+    /// new wraps a writer that receives data of the specified type.
+    pub fn new(dst: W, dtype: DType) -> Self {
+        let header = match dtype {
+            DType::DXH => Some(DxhHeader::new(DType::DDB)),
+            _ => None,
+        };
+        let wav_spec = match dtype {
+            DType::WAV => Some(WavSpec::default()),
+            _ => None,
+        };
+        let npy_header = match dtype {
+            DType::NPY => Some(NpyHeader::new(DType::DDB, &[0])),
+            _ => None,
+        };
+        let dtype = match dtype {
+            DType::DXH | DType::NPY => DType::DDB,
+            DType::WAV => DType::DSB,
+            _ => dtype,
+        };
+        DxxWriter {
+            dst: BufWriter::new(dst),
+            dtype,
+            header,
+            wav_spec,
+            npy_header,
+            seek: None,
+            declared_len: None,
+            placeholder: None,
+            started: false,
+            len: 0,
+            channels: 1,
+            quantizer: Quantizer::new(Quantization::default()),
+            endian: Endian::default(),
+        }
+    }
+
+    /// with_endian sets the byte order of DSB/DFB/DDB samples.
+    pub fn with_endian(mut self, endian: Endian) -> Self {
+        self.endian = endian;
+        self
+    }
+
+    /// with_channels sets the number of interleaved channels.
+    /// The text formats write one frame per line with the channels as columns.
+    /// The channel count of a pending DXH header or of a WAV spec is updated as well.
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        self.channels = channels;
+        if let Some(ref mut header) = self.header {
+            header.metadata.channels = channels;
+        }
+        if let Some(ref mut spec) = self.wav_spec {
+            spec.channels = channels;
+        }
+        self
+    }
+
+    /// with_header makes the stream a DXH stream with the specified header.
+    /// The byte order and the channel count of the header become those of the writer.
+    pub fn with_header(mut self, header: DxhHeader) -> Self {
+        self.dtype = header.dtype;
+        self.endian = header.endian;
+        self.channels = header.metadata.channels;
+        self.header = Some(header);
+        self
+    }
+
+    /// with_wav_spec makes the stream a WAV stream with the specified spec.
+    /// The channel count of the spec becomes the one of the writer.
+    pub fn with_wav_spec(mut self, spec: WavSpec) -> Self {
+        self.dtype = spec.dtype;
+        self.channels = spec.channels;
+        self.wav_spec = Some(spec);
+        self
+    }
+
+    /// with_npy_header makes the stream an NPY stream with the type and the byte order
+    /// of the specified header, and as many channels as its shape has columns.
+    /// The shape written by `finish` is the one of the samples pushed in C order.
+    pub fn with_npy_header(mut self, header: NpyHeader) -> Self {
+        self.dtype = header.dtype;
+        self.endian = header.endian;
+        self.channels = header.channels() as u16;
+        self.npy_header = Some(header);
+        self
+    }
+
+    /// with_len declares the number of samples that will be written, which lets a WAV or
+    /// NPY header be written up front without seeking. `finish` fails if another number
+    /// of samples has been written.
+    pub fn with_len(mut self, len: u64) -> Self {
+        self.declared_len = Some(len);
+        self
+    }
+
+    /// with_quantization sets how samples of the integer types are converted.
+    pub fn with_quantization(mut self, quantization: Quantization) -> Self {
+        self.quantizer = Quantizer::new(quantization);
+        self
+    }
+
+    /// dtype returns the data type of the stream.
+    /// For a DXH, WAV or NPY stream it is the payload type.
+    pub fn dtype(&self) -> DType {
+        self.dtype
+    }
+
+    /// len returns the number of samples written so far.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// is_empty reports whether no sample has been written yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// clipped returns the number of integer samples that have been clipped so far.
+    pub fn clipped(&self) -> u64 {
+        self.quantizer.clipped()
+    }
+
+    /// push writes a single sample.
+    pub fn push(&mut self, x: f64) -> Result<()> {
+        if !self.started {
+            self.start()?;
+        }
+        let separator = self.separator();
+        let endian = if self.wav_spec.is_some() { Endian::Little } else { self.endian };
+        encode_sample(&mut self.dst, x, self.dtype, endian, separator, &mut self.quantizer)?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// write_samples writes all samples of `src`.
+    pub fn write_samples(&mut self, src: &[f64]) -> Result<()> {
+        for &x in src {
+            self.push(x)?;
+        }
+        Ok(())
+    }
+
+    /// separator returns what follows the next text sample: a newline at the end of a frame.
+    fn separator(&self) -> char {
+        if (self.len + 1).is_multiple_of(u64::from(self.channels.max(1))) {
+            '\n'
+        } else {
+            ' '
+        }
+    }
+
+    /// start writes the header before the first sample. The header of a WAV or NPY stream
+    /// holds the declared length, or a placeholder if the writer can seek.
+    fn start(&mut self) -> Result<()> {
+        if let Some(header) = self.header.take() {
+            header.with_endian(self.endian).write(&mut self.dst)?;
+        }
+        if self.wav_spec.is_none() && self.npy_header.is_none() {
+            self.started = true;
+            return Ok(());
+        }
+        match (self.declared_len, self.seek) {
+            (Some(len), _) => {
+                let header = self.container_header(Some(len), 0)?;
+                self.dst.write_all(&header)?;
+            }
+            (None, Some(seek)) => {
+                self.dst.flush()?;
+                let pos = seek(self.dst.get_mut(), SeekFrom::Current(0))?;
+                let header = self.container_header(None, 0)?;
+                self.dst.write_all(&header)?;
+                self.placeholder = Some((pos, header.len() as u64));
+            }
+            (None, None) => {
+                let dtype = if self.wav_spec.is_some() { DType::WAV } else { DType::NPY };
+                return Err(Error::UnknownLength(dtype));
+            }
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    /// container_header returns the WAV or NPY header for `len` samples, padded to at least
+    /// `min_len` bytes. Without `len`, it returns a placeholder that is at least as long as
+    /// the header of any length.
+    fn container_header(&self, len: Option<u64>, min_len: u64) -> Result<Vec<u8>> {
+        let mut buf: Vec<u8> = Vec::new();
+        if let Some(spec) = self.wav_spec {
+            let data_len = len.unwrap_or(0).saturating_mul(u64::from(self.dtype.byte_width()));
+            spec.write(&mut buf, data_len)?;
+        }
+        if let Some(ref header) = self.npy_header {
+            let channels = usize::from(self.channels.max(1));
+            let frames = len.map_or(usize::MAX, |len| len as usize / channels);
+            let shape = if channels > 1 { vec![frames, channels] } else { vec![frames] };
+            let header = NpyHeader { shape, endian: self.endian, fortran_order: false, ..header.clone() };
+            header.write_padded(&mut buf, min_len)?;
+        }
+        Ok(buf)
+    }
+
+    /// finish flushes the buffered data and returns the underlying writer.
+    /// The header of a WAV or NPY stream written with a placeholder is patched.
+    /// A partial last frame is flushed as well but makes finish fail.
+    pub fn finish(mut self) -> Result<W> {
+        if !self.started {
+            // Without any sample, the length of the stream is known to be zero.
+            if self.declared_len.is_none() {
+                self.declared_len = Some(0);
+            }
+            self.start()?;
+        }
+        if self.wav_spec.is_some() && (self.len * u64::from(self.dtype.byte_width())) % 2 == 1 {
+            self.dst.write_u8(0)?;
+        }
+        if let (Some((pos, header_len)), Some(seek)) = (self.placeholder, self.seek) {
+            self.dst.flush()?;
+            let end = seek(self.dst.get_mut(), SeekFrom::Current(0))?;
+            seek(self.dst.get_mut(), SeekFrom::Start(pos))?;
+            let header = self.container_header(Some(self.len), header_len)?;
+            self.dst.write_all(&header)?;
+            self.dst.flush()?;
+            seek(self.dst.get_mut(), SeekFrom::Start(end))?;
+        }
+        let (len, channels) = (self.len, self.channels.max(1));
+        let declared_len = self.declared_len;
+        let w = match self.dst.into_inner() {
+            Ok(w) => w,
+            Err(e) => return Err(e.into_error().into()),
+        };
+        if let Some(want) = declared_len {
+            if want != len {
+                return Err(Error::LengthMismatch { want, got: len });
+            }
+        }
+        if !len.is_multiple_of(u64::from(channels)) {
+            return Err(Error::ChannelMismatch { path: None, len: len as usize, channels });
+        }
+        Ok(w)
+    }
+}
+
+/// encode_sample writes a sample as data of the specified type.
+/// `separator` follows a text sample.
+fn encode_sample<W: Write>(dst: &mut W, x: f64, dtype: DType, endian: Endian, separator: char, quantizer: &mut Quantizer) -> Result<()> {
+    match dtype {
+        DType::DSA => write!(dst, "{}{}", quantizer.quantize(x), separator)?,
+        DType::DFA => write!(dst, "{}{}", x as f32, separator)?,
+        DType::DDA => write!(dst, "{}{}", x, separator)?,
+
+        DType::DSB => {
+            let x = quantizer.quantize(x);
+            match endian {
+                Endian::Little => dst.write_i16::<LittleEndian>(x)?,
+                Endian::Big => dst.write_i16::<BigEndian>(x)?,
+            }
+        }
+        DType::DFB => match endian {
+            Endian::Little => dst.write_f32::<LittleEndian>(x as f32)?,
+            Endian::Big => dst.write_f32::<BigEndian>(x as f32)?,
+        },
+        DType::DDB => match endian {
+            Endian::Little => dst.write_f64::<LittleEndian>(x)?,
+            Endian::Big => dst.write_f64::<BigEndian>(x)?,
+        },
+        DType::DCB => {
+            let x = quantizer.quantize_bits(x, 8);
+            dst.write_i8(x as i8)?
+        }
+        DType::DTB => {
+            let x = quantizer.quantize_bits(x, 24);
+            match endian {
+                Endian::Little => dst.write_i24::<LittleEndian>(x)?,
+                Endian::Big => dst.write_i24::<BigEndian>(x)?,
+            }
+        }
+        DType::DLB => {
+            let x = quantizer.quantize_bits(x, 32);
+            match endian {
+                Endian::Little => dst.write_i32::<LittleEndian>(x)?,
+                Endian::Big => dst.write_i32::<BigEndian>(x)?,
+            }
+        }
+        DType::DUB => {
+            let x = quantizer.quantize(x);
+            dst.write_u8(encode_mulaw(x))?
+        }
+        DType::DAB => {
+            let x = quantizer.quantize(x);
+            dst.write_u8(encode_alaw(x))?
+        }
+        DType::DXH | DType::WAV | DType::NPY => unreachable!("{} payload", dtype),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_stream_round_trip() {
+        let src: Vec<f64> = vec![5., -2., 4., -3., 0.];
+        let dtypes = [
+            DType::DSA, DType::DFA, DType::DDA, DType::DSB, DType::DFB, DType::DDB,
+            DType::DCB, DType::DTB, DType::DLB, DType::DXH, DType::WAV, DType::NPY,
+        ];
+        for &dtype in &dtypes {
+            let mut writer = DxxWriter::new(Vec::new(), dtype).with_len(src.len() as u64);
+            writer.write_samples(&src).unwrap();
+            assert_eq!(writer.len(), 5);
+            let buf = writer.finish().unwrap();
+
+            let reader = DxxReader::new(buf.as_slice(), dtype);
+            let dst: Vec<f64> = reader.collect::<Result<_>>().unwrap();
+            assert_eq!(dst, src);
+        }
+    }
+
+    #[test]
+    fn test_stream_containers() {
+        use std::io::Cursor;
+
+        let src: Vec<f64> = vec![5., -2., 4., -3., 0., 1.];
+        for &dtype in &[DType::WAV, DType::NPY] {
+            let mut writer = DxxWriter::new(Vec::new(), dtype).with_channels(2).with_len(src.len() as u64);
+            writer.write_samples(&src).unwrap();
+            let want = writer.finish().unwrap();
+
+            // A seekable writer gets a placeholder header that finish patches in place.
+            let mut dst = Cursor::new(b"prefix".to_vec());
+            dst.set_position(6);
+            let mut writer = DxxWriter::new_seekable(dst, dtype).with_channels(2);
+            writer.write_samples(&src).unwrap();
+            let buf = writer.finish().unwrap();
+            assert_eq!(buf.position(), buf.get_ref().len() as u64);
+            let buf = buf.into_inner();
+            assert_eq!(&buf[..6], b"prefix");
+            let dst: Vec<f64> = DxxReader::new(&buf[6..], dtype).collect::<Result<_>>().unwrap();
+            assert_eq!(dst, src, "{}", dtype);
+            if dtype == DType::WAV {
+                assert_eq!(&buf[6..], want.as_slice());
+            }
+
+            // Without a declared length, a writer that cannot seek is refused.
+            let mut writer = DxxWriter::new(Vec::new(), dtype);
+            assert!(matches!(writer.push(1.), Err(Error::UnknownLength(d)) if d == dtype));
+            assert!(matches!(writer.push(1.), Err(Error::UnknownLength(_))));
+            let buf = DxxWriter::new(Vec::new(), dtype).finish().unwrap();
+            assert_eq!(DxxReader::new(buf.as_slice(), dtype).count(), 0);
+
+            let mut writer = DxxWriter::new(Vec::new(), dtype).with_len(4);
+            writer.write_samples(&src[..3]).unwrap();
+            assert!(matches!(writer.finish(), Err(Error::LengthMismatch { want: 4, got: 3 })));
+        }
+    }
+
+    #[test]
+    fn test_size_mismatch() {
+        let buf: Vec<u8> = vec![0, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3];
+        let mut reader = DxxReader::new(buf.as_slice(), DType::DFB);
+        assert_eq!(reader.next().unwrap().unwrap(), 0.);
+        assert_eq!(reader.next().unwrap().unwrap(), 0.);
+        match reader.next().unwrap() {
+            Err(Error::SizeMismatch { len, dtype, .. }) => {
+                assert_eq!(len, 11);
+                assert_eq!(dtype, DType::DFB);
+            }
+            r => panic!("want Error::SizeMismatch, got {:?}", r),
+        }
+        assert!(reader.next().is_none());
+
+        let mut reader = DxxReader::new(buf.as_slice(), DType::DFB).with_lenient(true);
+        assert_eq!(reader.by_ref().count(), 2);
+        assert_eq!(reader.warnings(), &[ReadWarning::TrailingBytes { len: 11, trailing: 3, width: 4 }]);
+    }
+
+    #[test]
+    fn test_io_error() {
+        struct Broken;
+
+        impl std::io::Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("broken"))
+            }
+        }
+
+        // A persistent I/O error ends the stream instead of being returned forever.
+        for &dtype in &[DType::DSB, DType::DDA] {
+            let mut reader = DxxReader::new(Broken, dtype);
+            assert!(matches!(reader.next(), Some(Err(Error::Io { .. }))));
+            assert!(reader.next().is_none());
+            let mut buf = [0.; 4];
+            assert_eq!(reader.read_chunk(&mut buf).unwrap(), 0);
+        }
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let buf = "1\n2.5\n-\n";
+        let mut reader = DxxReader::new(buf.as_bytes(), DType::DDA);
+        assert_eq!(reader.by_ref().take(2).collect::<Result<Vec<f64>>>().unwrap(), vec![1., 2.5]);
+        match reader.next().unwrap() {
+            Err(Error::Parse { line, text, .. }) => {
+                assert_eq!(line, 3);
+                assert_eq!(text, "-");
+            }
+            r => panic!("want Error::Parse, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_tolerant_text() {
+        let buf = "\u{feff}# exported by MATLAB\r\n  1.5 \r\n\r\n\t-2\r\n% comment\r\n3e2\r\n\r\n";
+        let dst: Vec<f64> = DxxReader::new(buf.as_bytes(), DType::DDA).collect::<Result<_>>().unwrap();
+        assert_eq!(dst, vec![1.5, -2., 300.]);
+
+        let buf = "signal\n1\n  2\n  x2\n";
+        let options = ReadOptions::new().with_header_lines(1);
+        let mut reader = DxxReader::new(buf.as_bytes(), DType::DSA).with_options(options);
+        assert_eq!(reader.by_ref().take(2).collect::<Result<Vec<f64>>>().unwrap(), vec![1., 2.]);
+        match reader.next().unwrap() {
+            Err(Error::Parse { line, column, text, .. }) => {
+                assert_eq!((line, column), (4, 3));
+                assert_eq!(text, "x2");
+            }
+            r => panic!("want Error::Parse, got {:?}", r),
+        }
+
+        let buf = "; comment\n1\n";
+        let options = ReadOptions::new().with_comment_prefixes(&[";"]);
+        let dst: Vec<f64> = DxxReader::new(buf.as_bytes(), DType::DDA).with_options(options).collect::<Result<_>>().unwrap();
+        assert_eq!(dst, vec![1.]);
+    }
+
+    #[test]
+    fn test_chunks() {
+        let src: Vec<f64> = (0..10).map(f64::from).collect();
+        let mut writer = DxxWriter::new(Vec::new(), DType::DSB);
+        writer.write_samples(&src).unwrap();
+        let buf = writer.finish().unwrap();
+
+        let chunks: Vec<Vec<f64>> = DxxReader::new(buf.as_slice(), DType::DSB)
+            .chunks(4)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(chunks, vec![vec![0., 1., 2., 3.], vec![4., 5., 6., 7.], vec![8., 9.]]);
+    }
+}
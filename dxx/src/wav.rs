@@ -0,0 +1,367 @@
+//! RIFF/WAVE files.
+//!
+//! A WAV file is read and written like a DXH file: its `fmt ` chunk names the sample type
+//! of the payload, which is one of the binary DXX types:
+//!
+//! | WAV format | payload |
+//! |------------|---------|
+//! | 16-bit PCM | DSB |
+//! | 24-bit PCM | DTB |
+//! | 32-bit PCM | DLB |
+//! | 32-bit IEEE float | DFB |
+//! | 64-bit IEEE float | DDB |
+//! | 8-bit mu-law | DUB |
+//! | 8-bit A-law | DAB |
+//!
+//! WAV files are always little-endian, and chunks other than `fmt ` and `data` are skipped.
+//! `WAVE_FORMAT_EXTENSIBLE` is read, and written for more than two channels or more than
+//! 16 integer bits as the format requires.
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::path::Path;
+
+use byteorder::{ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::dxh::Counter;
+use crate::error::{Error, Result};
+use crate::metadata::Metadata;
+use crate::options::{Level, WriteOptions, WriteReport};
+use crate::stream::DxxWriter;
+use crate::DType;
+
+/// DEFAULT_SAMPLING_RATE is the sampling rate of a WAV file written without one.
+pub const DEFAULT_SAMPLING_RATE: u32 = 48000;
+
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const FORMAT_ALAW: u16 = 0x0006;
+const FORMAT_MULAW: u16 = 0x0007;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+/// SUBFORMAT_TAIL is the part of the KSDATAFORMAT_SUBTYPE GUIDs that follows the format code.
+const SUBFORMAT_TAIL: [u8; 14] = [0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0x80, 0x00, 0x00, 0xAA, 0x00, 0x38, 0x9B, 0x71];
+
+/// WavSpec describes the samples of a WAV file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WavSpec {
+    /// dtype is the type of the payload: DSB, DTB, DLB, DFB, DDB, DUB or DAB.
+    pub dtype: DType,
+    /// channels is the number of interleaved channels.
+    pub channels: u16,
+    /// sampling_rate is the sampling frequency in Hz.
+    pub sampling_rate: u32,
+}
+
+impl Default for WavSpec {
+    fn default() -> Self {
+        WavSpec::new(DType::DSB)
+    }
+}
+
+impl WavSpec {
+    /// new returns a spec of a single channel at the default sampling rate.
+    pub fn new(dtype: DType) -> Self {
+        WavSpec { dtype, channels: 1, sampling_rate: DEFAULT_SAMPLING_RATE }
+    }
+
+    /// with_channels returns the spec with the specified number of channels.
+    pub fn with_channels(mut self, channels: u16) -> Self {
+        self.channels = channels;
+        self
+    }
+
+    /// with_sampling_rate returns the spec with the specified sampling rate.
+    pub fn with_sampling_rate(mut self, sampling_rate: u32) -> Self {
+        self.sampling_rate = sampling_rate;
+        self
+    }
+
+    /// from_metadata returns the spec of the specified payload type with the sampling rate
+    /// and the channel count of `metadata`.
+    pub fn from_metadata(dtype: DType, metadata: &Metadata) -> Self {
+        WavSpec {
+            dtype,
+            channels: metadata.channels,
+            sampling_rate: metadata.sampling_rate.unwrap_or(DEFAULT_SAMPLING_RATE),
+        }
+    }
+
+    /// metadata returns the sampling rate and the channel count as metadata.
+    pub fn metadata(&self) -> Metadata {
+        Metadata::new().with_sampling_rate(self.sampling_rate).with_channels(self.channels)
+    }
+
+    /// read reads the chunks of a WAV file up to the `data` chunk. It returns the spec,
+    /// the offset of the samples and the length of the `data` chunk in bytes.
+    /// `src` is left at the first byte of the samples.
+    pub fn read<R: Read>(src: &mut R) -> Result<(Self, u64, u64)> {
+        let mut src = Counter { inner: src, count: 0 };
+        let mut riff = [0u8; 12];
+        src.read_exact(&mut riff).map_err(truncated)?;
+        if &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+            return Err(invalid("not a RIFF/WAVE file"));
+        }
+
+        let mut spec: Option<WavSpec> = None;
+        loop {
+            let mut id = [0u8; 4];
+            src.read_exact(&mut id).map_err(|e| match e.kind() {
+                io::ErrorKind::UnexpectedEof => invalid("missing data chunk"),
+                _ => e.into(),
+            })?;
+            let size = src.read_u32::<LittleEndian>().map_err(truncated)?;
+            match &id {
+                b"fmt " => {
+                    let mut buf: Vec<u8> = Vec::new();
+                    (&mut src).take(u64::from(size)).read_to_end(&mut buf)?;
+                    if buf.len() != size as usize {
+                        return Err(invalid("truncated"));
+                    }
+                    spec = Some(parse_fmt(&buf)?);
+                    skip(&mut src, u64::from(size & 1))?;
+                }
+                b"data" => {
+                    let spec = spec.ok_or_else(|| invalid("data chunk before fmt chunk"))?;
+                    return Ok((spec, src.count, u64::from(size)));
+                }
+                _ => skip(&mut src, u64::from(size) + u64::from(size & 1))?,
+            }
+        }
+    }
+
+    /// write writes the RIFF header, the `fmt ` chunk and the header of a `data` chunk
+    /// of `data_len` bytes. The samples and a pad byte for an odd length have to follow.
+    pub fn write<W: Write>(&self, dst: &mut W, data_len: u64) -> Result<()> {
+        let (format, width) = self.format()?;
+        if self.channels == 0 {
+            return Err(Error::ChannelMismatch { path: None, len: 0, channels: 0 });
+        }
+        let bits = width * 8;
+        let block_align = u32::from(self.channels) * u32::from(width);
+        let block_align = u16::try_from(block_align).map_err(|_| invalid("too many channels"))?;
+        let extensible = (format == FORMAT_PCM || format == FORMAT_IEEE_FLOAT) && (self.channels > 2 || (format == FORMAT_PCM && bits > 16));
+        let fmt_len: u32 = if extensible { 40 } else if format == FORMAT_PCM { 16 } else { 18 };
+        // Formats other than PCM need a fact chunk with the number of frames.
+        let fact_len: u32 = if format == FORMAT_PCM { 0 } else { 12 };
+        let riff_len = 4 + (8 + u64::from(fmt_len)) + u64::from(fact_len) + 8 + data_len + (data_len & 1);
+        let riff_len = u32::try_from(riff_len).map_err(|_| invalid("too large for a WAV file"))?;
+
+        let mut buf: Vec<u8> = Vec::new();
+        buf.extend_from_slice(b"RIFF");
+        buf.write_u32::<LittleEndian>(riff_len)?;
+        buf.extend_from_slice(b"WAVE");
+        buf.extend_from_slice(b"fmt ");
+        buf.write_u32::<LittleEndian>(fmt_len)?;
+        buf.write_u16::<LittleEndian>(if extensible { FORMAT_EXTENSIBLE } else { format })?;
+        buf.write_u16::<LittleEndian>(self.channels)?;
+        buf.write_u32::<LittleEndian>(self.sampling_rate)?;
+        buf.write_u32::<LittleEndian>(self.sampling_rate.saturating_mul(u32::from(block_align)))?;
+        buf.write_u16::<LittleEndian>(block_align)?;
+        buf.write_u16::<LittleEndian>(bits)?;
+        if fmt_len > 16 {
+            buf.write_u16::<LittleEndian>(if extensible { 22 } else { 0 })?;
+        }
+        if extensible {
+            buf.write_u16::<LittleEndian>(bits)?;
+            let mask = if self.channels <= 18 { (1u32 << self.channels) - 1 } else { 0 };
+            buf.write_u32::<LittleEndian>(mask)?;
+            buf.write_u16::<LittleEndian>(format)?;
+            buf.extend_from_slice(&SUBFORMAT_TAIL);
+        }
+        if fact_len > 0 {
+            buf.extend_from_slice(b"fact");
+            buf.write_u32::<LittleEndian>(4)?;
+            buf.write_u32::<LittleEndian>((data_len / u64::from(block_align)) as u32)?;
+        }
+        buf.extend_from_slice(b"data");
+        buf.write_u32::<LittleEndian>(data_len as u32)?;
+        dst.write_all(&buf)?;
+        Ok(())
+    }
+
+    /// format returns the WAV format code and the byte width of the payload type.
+    fn format(&self) -> Result<(u16, u16)> {
+        let format = match self.dtype {
+            DType::DSB | DType::DTB | DType::DLB => FORMAT_PCM,
+            DType::DFB | DType::DDB => FORMAT_IEEE_FLOAT,
+            DType::DUB => FORMAT_MULAW,
+            DType::DAB => FORMAT_ALAW,
+            dtype => return Err(invalid(&format!("{} cannot be stored in a WAV file", dtype))),
+        };
+        Ok((format, self.dtype.byte_width() as u16))
+    }
+}
+
+/// read_wav_spec reads the spec of the specified WAV file.
+pub fn read_wav_spec<P: AsRef<Path>>(filename: P) -> Result<WavSpec> {
+    let filename = filename.as_ref();
+    let f = File::open(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let (spec, _, _) = WavSpec::read(&mut BufReader::new(f)).map_err(|e| e.with_path(filename))?;
+    Ok(spec)
+}
+
+/// write_wav writes samples to the specified WAV file with the specified spec.
+/// The samples are scaled for the payload type of the spec, and the byte order
+/// of the options is ignored.
+pub fn write_wav<P: AsRef<Path>>(filename: P, src: &[f64], spec: &WavSpec, options: &WriteOptions) -> Result<WriteReport> {
+    let filename = filename.as_ref();
+    spec.metadata().validate(src.len())?;
+    spec.format()?;
+    let (src, replaced) = options.check_finite(src)?;
+    let level = Level::measure(&[&src]);
+    options.check_silence(&level)?;
+
+    let samples = options.scaling.apply(&src, spec.dtype);
+    let f = File::create(filename).map_err(|e| Error::from(e).with_path(filename))?;
+    let mut writer = DxxWriter::new(f, DType::WAV)
+        .with_wav_spec(*spec)
+        .with_len(samples.len() as u64)
+        .with_quantization(options.quantization);
+    writer.write_samples(&samples).map_err(|e| e.with_path(filename))?;
+    let report = WriteReport { clipped: writer.clipped(), replaced, silent: level.is_silent() };
+    writer.finish().map_err(|e| e.with_path(filename))?;
+    Ok(report)
+}
+
+/// parse_fmt parses the body of a `fmt ` chunk.
+fn parse_fmt(buf: &[u8]) -> Result<WavSpec> {
+    if buf.len() < 16 {
+        return Err(invalid("fmt chunk too short"));
+    }
+    let mut format = LittleEndian::read_u16(&buf[0..]);
+    let channels = LittleEndian::read_u16(&buf[2..]);
+    let sampling_rate = LittleEndian::read_u32(&buf[4..]);
+    let block_align = LittleEndian::read_u16(&buf[12..]);
+    let bits = LittleEndian::read_u16(&buf[14..]);
+    if format == FORMAT_EXTENSIBLE {
+        if buf.len() < 40 {
+            return Err(invalid("extensible fmt chunk too short"));
+        }
+        format = LittleEndian::read_u16(&buf[24..]);
+    }
+    let dtype = match (format, bits) {
+        (FORMAT_PCM, 16) => DType::DSB,
+        (FORMAT_PCM, 24) => DType::DTB,
+        (FORMAT_PCM, 32) => DType::DLB,
+        (FORMAT_IEEE_FLOAT, 32) => DType::DFB,
+        (FORMAT_IEEE_FLOAT, 64) => DType::DDB,
+        (FORMAT_MULAW, 8) => DType::DUB,
+        (FORMAT_ALAW, 8) => DType::DAB,
+        _ => return Err(invalid(&format!("unsupported format {:#06x} with {} bits", format, bits))),
+    };
+    if channels == 0 {
+        return Err(invalid("zero channels"));
+    }
+    if u32::from(block_align) != u32::from(channels) * dtype.byte_width() {
+        return Err(invalid(&format!("block align {} does not fit {} channels of {}", block_align, channels, dtype)));
+    }
+    Ok(WavSpec { dtype, channels, sampling_rate })
+}
+
+fn skip<R: Read>(src: &mut R, len: u64) -> Result<()> {
+    let n = io::copy(&mut src.take(len), &mut io::sink())?;
+    if n != len {
+        return Err(invalid("truncated"));
+    }
+    Ok(())
+}
+
+fn invalid(reason: &str) -> Error {
+    Error::InvalidWav(reason.to_string())
+}
+
+fn truncated(e: io::Error) -> Error {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        return invalid("truncated");
+    }
+    e.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn test_wav_formats() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = vec![1000., -2000., 3000., -4000., 5000., -6000.];
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+
+        for &dtype in &[DType::DSB, DType::DTB, DType::DLB, DType::DFB, DType::DDB] {
+            for &channels in &[1, 2, 3] {
+                let filename = dir.join(format!("{}_{}.wav", dtype, channels));
+                let spec = WavSpec::new(dtype).with_channels(channels).with_sampling_rate(44100);
+                write_wav(&filename, &src, &spec, &options).unwrap();
+                assert_eq!(read_wav_spec(&filename).unwrap(), spec);
+                assert_eq!(read_file(&filename).unwrap(), src, "{} {}", dtype, channels);
+                assert_eq!(read_metadata(&filename).unwrap(), Some(spec.metadata()));
+                assert_eq!(read_range(&filename, 2, 3).unwrap(), &src[2..5]);
+            }
+        }
+
+        // A DTB payload of an odd length is padded to an even chunk.
+        write_wav(dir.join("odd.wav"), &src[..1], &WavSpec::new(DType::DTB), &options).unwrap();
+        assert_eq!(len_file(dir.join("odd.wav")).unwrap() % 2, 0);
+        assert_eq!(read_file(dir.join("odd.wav")).unwrap(), vec![1000.]);
+
+        let e = write_wav(dir.join("a.wav"), &src, &WavSpec::new(DType::DDA), &options).unwrap_err();
+        assert!(matches!(e, Error::InvalidWav(_)));
+        let e = write_wav(dir.join("a.wav"), &src, &WavSpec::new(DType::DSB).with_channels(4), &options).unwrap_err();
+        assert!(matches!(e, Error::ChannelMismatch { len: 6, channels: 4, .. }));
+    }
+
+    #[test]
+    fn test_wav_chunks() {
+        // A canonical 16-bit stereo file with a LIST chunk before the data chunk.
+        let mut buf: Vec<u8> = Vec::new();
+        buf.extend_from_slice(b"RIFF\x32\x00\x00\x00WAVE");
+        buf.extend_from_slice(b"fmt \x10\x00\x00\x00\x01\x00\x02\x00\x44\xac\x00\x00\x10\xb1\x02\x00\x04\x00\x10\x00");
+        buf.extend_from_slice(b"LIST\x03\x00\x00\x00abc\x00");
+        buf.extend_from_slice(b"data\x04\x00\x00\x00\x01\x00\xff\xff");
+        let (spec, offset, len) = WavSpec::read(&mut buf.as_slice()).unwrap();
+        assert_eq!(spec, WavSpec::new(DType::DSB).with_channels(2).with_sampling_rate(44100));
+        assert_eq!((offset, len), (buf.len() as u64 - 4, 4));
+        assert_eq!(read_bytes(&buf, DType::WAV).unwrap(), vec![1., -1.]);
+
+        // Chunks after the data chunk are not samples.
+        buf.extend_from_slice(b"id3 \x02\x00\x00\x00xy");
+        assert_eq!(read_bytes(&buf, DType::WAV).unwrap(), vec![1., -1.]);
+
+        assert!(matches!(WavSpec::read(&mut &buf[..30]), Err(Error::InvalidWav(_))));
+        assert!(matches!(WavSpec::read(&mut &b"DXH\0...."[..]), Err(Error::InvalidWav(_))));
+        let mut unsupported = buf.clone();
+        unsupported[34] = 8;
+        assert!(matches!(WavSpec::read(&mut unsupported.as_slice()), Err(Error::InvalidWav(_))));
+    }
+
+    #[test]
+    fn test_wav_conversion() {
+        let tmp = test_dir();
+        let dir = tmp.path();
+        let src: Vec<f64> = vec![0.5, -0.25, 0.125, -1.];
+        let options = WriteOptions::new().with_scaling(Scaling::Raw);
+        let metadata = Metadata::new().with_sampling_rate(16000).with_channels(2);
+        write_file_with_metadata(dir.join("a.DDB"), &src, &metadata, &options).unwrap();
+
+        // write_file normalizes a WAV file for 16-bit PCM.
+        convert(dir.join("a.DDB"), dir.join("a.wav")).unwrap();
+        assert_eq!(read_wav_spec(dir.join("a.wav")).unwrap(), WavSpec::new(DType::DSB).with_channels(2).with_sampling_rate(16000));
+        assert_eq!(read_file(dir.join("a.wav")).unwrap(), vec![16384., -8192., 4096., -32767.]);
+
+        convert_with(dir.join("a.wav"), dir.join("b.DSB"), &options).unwrap();
+        assert_eq!(read_file(dir.join("b.DSB")).unwrap(), vec![16384., -8192., 4096., -32767.]);
+        assert_eq!(read_metadata(dir.join("b.DSB")).unwrap(), Some(metadata.clone()));
+
+        // A DXH payload is kept by from_dxh and to_dxh.
+        let header = DxhHeader::new(DType::DFB).with_metadata(metadata.clone());
+        write_dxh(dir.join("c.DXH"), &src, &header, &options).unwrap();
+        from_dxh(dir.join("c.DXH"), dir.join("c.wav")).unwrap();
+        assert_eq!(read_wav_spec(dir.join("c.wav")).unwrap().dtype, DType::DFB);
+        assert_eq!(read_file(dir.join("c.wav")).unwrap(), src);
+        to_dxh(dir.join("c.wav"), dir.join("d.DXH")).unwrap();
+        assert_eq!(read_dxh(dir.join("d.DXH")).unwrap(), (src, header));
+    }
+}
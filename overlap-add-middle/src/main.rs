@@ -15,6 +15,7 @@ struct Opt {
     /// Sound file that convolve the transfer function.
     /// Typically white noise is used.
     /// i.e. `path/to/wXXs.DSB`
+    /// FLAC, Ogg Vorbis and MP3 are accepted when built with the `decode` feature.
     sound_file: PathBuf,
 
     /// Moving width [10^-1 deg].
@@ -68,96 +69,65 @@ fn overlap_add(opt: Opt) -> Result<()> {
         None => return Err(Error::msg("output is empty"))
     };
 
-    // サンプリング周波数 [sample/sec]
-    let sampling_freq = 48000;
-    // 移動時間 [sec]
-    let move_time: f64 = move_width as f64 / move_velocity as f64;
-    // 移動時間 [sample]
-    let move_samples: u32 = (move_time * sampling_freq as f64) as u32;
-
-    // 0.1度動くのに必要なサンプル数
-    // [sec]*[sample/sec] / [0.1deg] = [sample/0.1deg]
-    let move_samples_per_deg: u32 = move_samples / move_width;
+    // 音データのメタデータ
+    // DXX/DXH/WAV/NPY はヘッダかサイドカーファイルから読み込む
+    // それ以外 (decode feature が有効なら FLAC/Ogg/MP3) は全体をデコードして得る
+    let decoded = match dxx::DType::from_path(sound_file) {
+        Ok(_) => None,
+        Err(_) => Some(dxx::read_audio(sound_file)?),
+    };
+    let sound_meta = match &decoded {
+        Some((_, meta)) => meta.clone(),
+        None => dxx::read_metadata(sound_file)?.unwrap_or_default(),
+    };
 
-    // 音データの読み込み
-    let sound = dxx::read_file(sound_file)?;
+    // サンプリング周波数 [sample/sec]
+    // 音データのメタデータがあればその値を使い、なければ 48000 とする
+    if sound_meta.channels != 1 {
+        return Err(Error::msg("sound_file must be monaural"));
+    }
+    let sampling_freq = sound_meta.sampling_rate.unwrap_or(48000);
+    let movement = movement(move_width, move_velocity, angle);
+    movement.check(sampling_freq)?;
+
+    // 音データの読み込み (畳み込みに使う区間のみ)
+    let sound_len = movement.sound_len(sampling_freq) as u64;
+    let sound = match decoded {
+        Some((sound, _)) => {
+            if (sound.len() as u64) < sound_len {
+                return Err(Error::msg("sound_file is shorter than the movement"));
+            }
+            sound
+        }
+        None => dxx::read_range(sound_file, 0, sound_len)?,
+    };
 
-    let sltf_name = format!("{}/SLTF/SLTF_{}_{}.DDB", subject, 0, "L");
-    let sltf = dxx::read_file(sltf_name.as_str())?;
+    // 全出力を同じゲインで正規化するため、書き出しは最後にまとめて行う
+    let mut outputs: Vec<(String, Vec<f64>)> = Vec::with_capacity(4);
 
     for direction in ["c", "cc"].iter() {
         for lr in ["L", "R"].iter() {
-            let mut move_out: Vec<f64> = vec![0.; (move_samples + sltf.len() as u32 - 1) as usize];
-
-            // 使用する角度の計算
-            let clockwise = *direction == "c";
-            let angles = calc_angles(&move_width, &angle, clockwise);
-
-            for (i, conv_angle) in angles.iter().enumerate() {
-                let i = i as u32;
-                // SLTFの読み込み
-                let sltf_name = format!("{}/SLTF/SLTF_{}_{}.DDB", subject, conv_angle, lr);
-                let sltf = dxx::read_file(sltf_name.as_str())?;
-
-                // 音データと伝達関数の畳込み
-                let cut_sound = sound[(move_samples_per_deg * i) as usize..(move_samples_per_deg * (i + 1)) as usize].to_vec();
-                let sound_sltf = linear_conv(cut_sound, sltf);
-                // Overlap-Add
-                for (j, v) in sound_sltf.iter().enumerate() {
-                    let j = j as u32;
-                    move_out[(move_samples_per_deg * i + j) as usize] += v
-                }
-            }
+            // 使用する角度の計算と、SLTF との畳み込み・Overlap-Add
+            let movement = movement.clone().with_clockwise(*direction == "c");
+            let move_out = dxx::render_moving(subject, &sound, sampling_freq, &movement, lr)?;
 
             let output_name = format!("{}/move_judge_w{:>04}_mt{:>04}_{}_{:>04}_{}.DDB", output, move_width, move_velocity, direction, angle, lr);
-            let output_len = move_out.len();
-            dxx::write_file(output_name.as_str(), move_out)?;
-            eprintln!("{}, length={}", output_name, output_len);
-            eprintln!("angles={:?}", angles)
+            eprintln!("{}, length={}", output_name, move_out.len());
+            eprintln!("angles={:?}", movement.angles());
+            outputs.push((output_name, move_out));
         };
     };
+    dxx::write_files(&outputs)?;
+    let metadata = dxx::Metadata::new()
+        .with_sampling_rate(sampling_freq)
+        .with_creator(env!("CARGO_PKG_NAME"));
+    for (output_name, _) in outputs.iter() {
+        dxx::write_metadata(output_name, &metadata)?;
+    }
     Ok(())
 }
 
-fn calc_angles(move_width: &u32, angle: &u32, clockwise: bool) -> Vec<i32> {
-    let move_width = *move_width as i32;
-    let angle = *angle as i32;
-    let start_angle = if clockwise {
-        angle - move_width / 2
-    } else {
-        angle + move_width / 2 - 1
-    };
-
-    let start_angle = if start_angle < 0 {
-        start_angle + 3600
-    } else {
-        start_angle
-    } % 3600;
-
-    let mut angles = Vec::with_capacity(move_width as usize);
-    for i in 0..move_width {
-        let mut data_angle = i % (move_width * 2);
-        if data_angle > move_width {
-            data_angle = move_width * 2 - data_angle
-        }
-        if !clockwise {
-            data_angle = -1 * data_angle
-        }
-        if data_angle < 0 {
-            data_angle += 3600
-        }
-        angles.push((start_angle + data_angle) % 3600);
-    }
-    angles
+/// movement returns the movement of this tool: 0.1 degree steps centered on `angle`.
+fn movement(move_width: u32, move_velocity: u32, angle: u32) -> dxx::Movement {
+    dxx::Movement::new(move_width, move_velocity, angle).with_anchor(dxx::Anchor::Middle).with_steps(3600)
 }
-
-fn linear_conv(x: Vec<f64>, y: Vec<f64>) -> Vec<f64> {
-    let conv_len = x.len() + y.len() - 1;
-    let mut ret: Vec<f64> = vec!(0.; conv_len);
-    for p in 0..x.len() {
-        for n in p..y.len() + p {
-            ret[n] += x[p] * y[n - p];
-        }
-    }
-    ret
-}
\ No newline at end of file